@@ -0,0 +1,43 @@
+//! Known vulnerabilities of formula versions, looked up in the OSV database.
+//!
+//! Homebrew is not an OSV ecosystem of its own, so formulas are looked up by name in another one,
+//! by default the OSS-Fuzz projects, which most C libraries and tools are named the same in.
+
+use crate::client::GithubClient;
+use crate::error::{BrewverError, Result};
+
+pub const OSV_QUERY_URL: &str = "https://api.osv.dev/v1/query";
+pub const DEFAULT_OSV_ECOSYSTEM: &str = "OSS-Fuzz";
+
+/// A vulnerability OSV knows to affect a version.
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct Advisory {
+    /// The OSV ID, e.g. `OSV-2021-1234`.
+    pub id: String,
+    /// Other IDs of the vulnerability, such as its CVE.
+    pub aliases: Vec<String>,
+    pub summary: Option<String>,
+}
+
+/// The vulnerabilities OSV lists for the version of the package named like the formula in the
+/// ecosystem.
+pub fn advisories(client: &dyn GithubClient, name: &str, version: &str, ecosystem: &str) -> Result<Vec<Advisory>> {
+    let query = serde_json::json!({ "package": { "name": name, "ecosystem": ecosystem }, "version": version });
+    let response = client.post(OSV_QUERY_URL, &query)?;
+    if !response.is_success() {
+        return Err(BrewverError::HttpStatus { url: response.url, status: response.status });
+    }
+    let json = response.json()?;
+    let strings = |value: Option<&serde_json::Value>| -> Vec<String> {
+        value.and_then(|v| v.as_array())
+            .map(|values| values.iter().filter_map(|v| v.as_str().map(String::from)).collect())
+            .unwrap_or_default()
+    };
+    Ok(json.get("vulns").and_then(|vulns| vulns.as_array()).into_iter().flatten()
+        .filter_map(|vuln| Some(Advisory {
+            id: vuln.get("id")?.as_str()?.to_string(),
+            aliases: strings(vuln.get("aliases")),
+            summary: vuln.get("summary").and_then(|v| v.as_str()).map(String::from),
+        }))
+        .collect())
+}
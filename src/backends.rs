@@ -0,0 +1,124 @@
+//! The ways of mapping a formula version to the commit of its formula file, behind the
+//! [`VersionResolver`] trait. A [`FormulaResolver`] tries the ones of its
+//! [`FormulaResolver::chain`] in turn, so resolvers for mirrors or private indices can be added
+//! next to the built-in ones.
+
+use crate::error::{BrewverError, Result};
+use crate::resolver::{is_tap, FormulaResolver};
+
+/// The names of the built-in resolvers, as [`builtin`] takes them.
+pub const BUILTIN_RESOLVERS: [&str; 4] = ["rest", "graphql", "git", "brew-api"];
+
+/// Finds the commit of a formula version.
+///
+/// ```no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use brewver::{BrewverError, FormulaResolver, VersionResolver};
+///
+/// /// Knows where the versions of an internal tap are from a list of its own.
+/// struct Index;
+///
+/// impl VersionResolver for Index {
+///     fn name(&self) -> &str {
+///         "index"
+///     }
+///
+///     fn find_commit(&self, resolver: &FormulaResolver, repo: &str, name: &str, version: &str) -> brewver::Result<(String, String)> {
+///         Err(BrewverError::CommitNotFound {
+///             name: name.to_string(),
+///             version: version.to_string(),
+///             repo: repo.to_string(),
+///             candidates: resolver.candidate_paths(repo, name),
+///         })
+///     }
+/// }
+///
+/// let client = brewver::Client::new();
+/// let chain = vec![Box::new(Index) as Box<dyn VersionResolver>, brewver::builtin_resolver("rest").unwrap()];
+/// let formula = FormulaResolver::new(&client).chain(chain).resolve("wget", "1.21.3")?;
+/// # Ok(())
+/// # }
+/// ```
+pub trait VersionResolver {
+    /// The name of the resolver, for logs and the trail of a resolution.
+    fn name(&self) -> &str;
+
+    /// Finds the commit of the version of formula (or cask) `name` in the repository, and the path
+    /// of its file at that commit. Versions it doesn't know fail with
+    /// [`BrewverError::CommitNotFound`]; on any error the next resolver of the chain is tried.
+    fn find_commit(&self, resolver: &FormulaResolver, repo: &str, name: &str, version: &str) -> Result<(String, String)>;
+}
+
+/// Searches the commit history through the GitHub REST API for the commit of the version, as the
+/// match strategy of the [`FormulaResolver`] says.
+pub struct RestResolver;
+
+impl VersionResolver for RestResolver {
+    fn name(&self) -> &str {
+        "rest"
+    }
+
+    fn find_commit(&self, resolver: &FormulaResolver, repo: &str, name: &str, version: &str) -> Result<(String, String)> {
+        resolver.find_commit(repo, name, version, false)
+    }
+}
+
+/// Searches the commit history like [`RestResolver`], through the GitHub GraphQL API, which needs
+/// a token. Without one the REST API is used.
+pub struct GraphqlResolver;
+
+impl VersionResolver for GraphqlResolver {
+    fn name(&self) -> &str {
+        "graphql"
+    }
+
+    fn find_commit(&self, resolver: &FormulaResolver, repo: &str, name: &str, version: &str) -> Result<(String, String)> {
+        resolver.find_commit(repo, name, version, true)
+    }
+}
+
+/// Searches the history of the local clone of the tap, found through `brew --repository`.
+pub struct GitResolver;
+
+impl VersionResolver for GitResolver {
+    fn name(&self) -> &str {
+        "git"
+    }
+
+    fn find_commit(&self, resolver: &FormulaResolver, repo: &str, name: &str, version: &str) -> Result<(String, String)> {
+        resolver.find_commit_in_clone(repo, name, version)
+    }
+}
+
+/// Asks the formulae.brew.sh API, which only knows the current version of the formulas and casks
+/// of homebrew-core and homebrew-cask.
+pub struct BrewApiResolver;
+
+impl VersionResolver for BrewApiResolver {
+    fn name(&self) -> &str {
+        "brew-api"
+    }
+
+    fn find_commit(&self, resolver: &FormulaResolver, repo: &str, name: &str, version: &str) -> Result<(String, String)> {
+        if is_tap(repo) {
+            return Err(BrewverError::CommitNotFound {
+                name: name.to_string(),
+                version: version.to_string(),
+                repo: repo.to_string(),
+                candidates: resolver.candidate_paths(repo, name),
+            });
+        }
+        resolver.find_commit_in_brew_api(name, version)
+    }
+}
+
+/// The built-in resolver of one of [`BUILTIN_RESOLVERS`].
+pub fn builtin(name: &str) -> Option<Box<dyn VersionResolver>> {
+    match name {
+        "rest" => Some(Box::new(RestResolver)),
+        "graphql" => Some(Box::new(GraphqlResolver)),
+        "git" => Some(Box::new(GitResolver)),
+        "brew-api" => Some(Box::new(BrewApiResolver)),
+        _ => None,
+    }
+}
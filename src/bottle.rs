@@ -0,0 +1,260 @@
+//! Bottles downloaded straight from the registry a formula file declares, so an old version is
+//! poured instead of built from source once brew's own API no longer knows its bottles.
+
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use log::{debug, info, warn};
+use tempfile::TempDir;
+
+use crate::cache::Cache;
+use crate::client::GithubClient;
+use crate::error::{BrewverError, Result};
+use crate::formula::{sha256_file, Bottle, Formula, FormulaFile};
+use crate::installer::{brew_command, rosetta_args, run_command};
+use crate::interrupt;
+
+/// Where homebrew-core publishes its bottles unless the `bottle do` block sets a `root_url`.
+pub const DEFAULT_BOTTLE_ROOT_URL: &str = "https://ghcr.io/v2/homebrew/core";
+
+static BOTTLE_DOMAIN: OnceLock<String> = OnceLock::new();
+
+/// Downloads the bottles homebrew-core publishes from a mirror, such as
+/// `https://mirrors.ustc.edu.cn/homebrew-bottles`, instead of `HOMEBREW_BOTTLE_DOMAIN` or the
+/// canonical registry. Has no effect once a bottle was looked up.
+pub fn set_bottle_domain(domain: &str) {
+    let _ = BOTTLE_DOMAIN.set(domain.trim_end_matches('/').to_string());
+}
+
+/// The mirror of homebrew-core's bottles, if one is set, as brew reads it.
+fn bottle_domain() -> Option<&'static str> {
+    static ENV: OnceLock<Option<String>> = OnceLock::new();
+    let domain = match BOTTLE_DOMAIN.get() {
+        Some(domain) => domain.as_str(),
+        None => ENV.get_or_init(|| {
+            std::env::var("HOMEBREW_BOTTLE_DOMAIN").ok().map(|domain| domain.trim_end_matches('/').to_string())
+        }).as_deref()?,
+    };
+    Some(domain).filter(|domain| !domain.is_empty() && *domain != DEFAULT_BOTTLE_ROOT_URL)
+}
+
+/// macOS releases by major version, newest first, as bottle tags name them.
+const MACOS_RELEASES: &[(u32, &str)] = &[
+    (26, "tahoe"),
+    (15, "sequoia"),
+    (14, "sonoma"),
+    (13, "ventura"),
+    (12, "monterey"),
+    (11, "big_sur"),
+    (10, "catalina"),
+];
+
+/// The platforms bottles are published for, as [`bottle_coverage`] summarizes them.
+pub const BOTTLE_PLATFORMS: [&str; 4] = ["arm64 macOS", "x86_64 macOS", "arm64 Linux", "x86_64 Linux"];
+
+/// Which bottles of a formula file are there for each of [`BOTTLE_PLATFORMS`], in its order: the
+/// macOS releases they span, such as `big_sur to sequoia`, `yes` on Linux, `all` for a bottle that
+/// pours on every platform, or `None` where the formula is built from source.
+pub fn bottle_coverage(bottles: &[Bottle]) -> Vec<Option<String>> {
+    let has_tag = |tag: &str| bottles.iter().any(|bottle| bottle.tag == tag);
+    BOTTLE_PLATFORMS.iter().map(|platform| {
+        if has_tag("all") {
+            return Some("all".to_string());
+        }
+        let (arch, os) = platform.split_once(' ').unwrap_or_default();
+        if os == "Linux" {
+            return has_tag(&format!("{}_linux", arch)).then(|| "yes".to_string());
+        }
+        let releases: Vec<&str> = MACOS_RELEASES.iter()
+            .map(|(_, name)| *name)
+            .filter(|name| has_tag(&if arch == "arm64" { format!("arm64_{}", name) } else { name.to_string() }))
+            .collect();
+        match (releases.first(), releases.last()) {
+            (Some(newest), Some(oldest)) if newest == oldest => Some(newest.to_string()),
+            (Some(newest), Some(oldest)) => Some(format!("{} to {}", oldest, newest)),
+            _ => None,
+        }
+    }).collect()
+}
+
+/// The bottle of a formula version for one platform, as declared in its formula file.
+#[derive(Clone, Debug)]
+pub struct BottleDownload {
+    pub url: String,
+    /// The file name brew expects for a local bottle, `<name>--<version>.<tag>.bottle.tar.gz`.
+    pub file_name: String,
+    pub tag: String,
+    pub sha256: String,
+    /// The URL in the canonical registry when `url` is on a mirror, tried when the mirror doesn't
+    /// have the bottle.
+    pub canonical_url: Option<String>,
+}
+
+impl BottleDownload {
+    /// The bottle in the formula file that pours on this machine: the one for its platform, else
+    /// one for an older macOS release, else one for `all` platforms. Bottles built for another
+    /// Cellar than brew's, such as one for `/home/linuxbrew/.linuxbrew` under another prefix, are
+    /// skipped, since brew refuses to pour them.
+    pub fn find(formula: &Formula, file: &FormulaFile) -> Result<Option<Self>> {
+        Self::find_for(formula, file, false)
+    }
+
+    /// The bottle in the formula file that pours with the Intel brew under Rosetta, on a Mac with
+    /// Apple Silicon.
+    pub fn find_rosetta(formula: &Formula, file: &FormulaFile) -> Result<Option<Self>> {
+        Self::find_for(formula, file, true)
+    }
+
+    fn find_for(formula: &Formula, file: &FormulaFile, rosetta: bool) -> Result<Option<Self>> {
+        let Some(tags) = compatible_tags(rosetta) else {
+            return Ok(None);
+        };
+        let info = file.info()?;
+        let Some(version) = Some(formula.version.clone()).filter(|version| !version.is_empty()).or(info.version) else {
+            return Ok(None);
+        };
+        let cellar = std::cell::OnceCell::new();
+        let pours = |bottle: &&Bottle| match bottle.cellar.as_deref() {
+            Some(path) if path.starts_with('/') => {
+                cellar.get_or_init(|| brew_cellar(rosetta)).as_deref() == Some(path)
+            }
+            _ => true,
+        };
+        let Some(bottle) = tags.iter().find_map(|tag| info.bottles.iter().filter(pours).find(|bottle| bottle.tag == *tag)) else {
+            return Ok(None);
+        };
+
+        let rebuild = info.bottle_rebuild.filter(|rebuild| *rebuild > 0).map(|rebuild| format!(".{}", rebuild)).unwrap_or_default();
+        let file_name = format!("{}--{}.{}.bottle{}.tar.gz", formula.brew_name, version, bottle.tag, rebuild);
+        let root_url = info.bottle_root_url.as_deref().unwrap_or(DEFAULT_BOTTLE_ROOT_URL).trim_end_matches('/');
+        let url_at = |root_url: &str| if root_url.starts_with("https://ghcr.io/") {
+            format!("{}/{}/blobs/sha256:{}", root_url, image_name(&formula.brew_name), bottle.sha256)
+        } else {
+            format!("{}/{}", root_url, file_name)
+        };
+        // Like brew, only bottles published to homebrew-core's registry are taken from the mirror.
+        let (url, canonical_url) = match (bottle_domain(), root_url.strip_prefix(DEFAULT_BOTTLE_ROOT_URL)) {
+            (Some(domain), Some(path)) => (url_at(&format!("{}{}", domain, path)), Some(url_at(root_url))),
+            _ => (url_at(root_url), None),
+        };
+        Ok(Some(BottleDownload { url, file_name, tag: bottle.tag.clone(), sha256: bottle.sha256.clone(), canonical_url }))
+    }
+
+    /// Downloads the bottle into a temporary directory that lives as long as the returned file, and
+    /// checks it against the checksum in the formula file. A bottle missing from the mirror is
+    /// downloaded from the canonical registry instead. Bottles are kept in the client's cache, and
+    /// taken from it when they were downloaded before.
+    pub fn download(&self, client: &dyn GithubClient) -> Result<BottleFile> {
+        let dir = interrupt::temp_dir()?;
+        let bottle = BottleFile { path: dir.path().join(&self.file_name), _dir: dir };
+        let from_cache = |cache: &Cache| -> Result<bool> {
+            let Some(cached) = cache.bottle(&self.sha256) else {
+                return Ok(false);
+            };
+            std::fs::copy(cached, &bottle.path)?;
+            if sha256_file(&bottle.path)? == self.sha256 {
+                return Ok(true);
+            }
+            warn!("The cached {} bottle doesn't match its checksum", self.tag);
+            Ok(false)
+        };
+        if let Some(cache) = client.cache().filter(|cache| cache.reads_first()) {
+            if from_cache(cache)? {
+                return Ok(bottle);
+            }
+        }
+        let downloaded = match (client.download_file(&self.url, &bottle.path), &self.canonical_url) {
+            (Ok(()), _) => Ok(&self.url),
+            (Err(BrewverError::HttpStatus { status: 404, .. }), Some(canonical_url)) => {
+                info!("The mirror has no {} bottle at {}, downloading it from {}", self.tag, self.url, canonical_url);
+                client.download_file(canonical_url, &bottle.path).map(|()| canonical_url)
+            }
+            (Err(e), _) => Err(e),
+        };
+        let url = match downloaded {
+            Err(e) if e.is_network() => {
+                match client.cache().filter(|cache| cache.reads_on_failure()) {
+                    Some(cache) if from_cache(cache)? => return Ok(bottle),
+                    _ => return Err(e),
+                }
+            }
+            url => url?,
+        };
+        debug!("Downloaded {} to {:?}", url, bottle.path);
+
+        let found = sha256_file(&bottle.path)?;
+        if found != self.sha256 {
+            return Err(BrewverError::BottleChecksumMismatch { url: url.clone(), expected: self.sha256.clone(), found });
+        }
+        if let Some(cache) = client.cache() {
+            if let Err(e) = cache.store_bottle(&self.sha256, &bottle.path) {
+                warn!("Failed to cache the bottle: {}", e);
+            }
+        }
+        Ok(bottle)
+    }
+}
+
+/// A downloaded bottle, removed together with its directory when dropped.
+pub struct BottleFile {
+    _dir: TempDir,
+    path: PathBuf,
+}
+
+impl Drop for BottleFile {
+    fn drop(&mut self) {
+        interrupt::remove_temp_dir(self._dir.path());
+    }
+}
+
+impl BottleFile {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// The bottle tags that pour on this machine, or under Rosetta, best first, or `None` on a
+/// platform without bottles.
+fn compatible_tags(rosetta: bool) -> Option<Vec<String>> {
+    if rosetta && !is_apple_silicon() {
+        return None;
+    }
+    let arch = match std::env::consts::ARCH {
+        "aarch64" if rosetta => "x86_64",
+        "aarch64" => "arm64",
+        "x86_64" => "x86_64",
+        _ => return None,
+    };
+    let mut tags = match std::env::consts::OS {
+        "linux" => vec![format!("{}_linux", arch)],
+        "macos" => {
+            let output = run_command("sw_vers", &["-productVersion"]).ok()?;
+            let release = String::from_utf8_lossy(&output.stdout);
+            let major: u32 = release.trim().split('.').next()?.parse().ok()?;
+            MACOS_RELEASES.iter()
+                .filter(|(release, _)| *release <= major)
+                .map(|(_, name)| if arch == "arm64" { format!("arm64_{}", name) } else { name.to_string() })
+                .collect()
+        }
+        _ => return None,
+    };
+    tags.push("all".to_string());
+    Some(tags)
+}
+
+/// Whether this is a Mac with Apple Silicon, which can run x86_64 code under Rosetta.
+pub fn is_apple_silicon() -> bool {
+    std::env::consts::OS == "macos" && std::env::consts::ARCH == "aarch64"
+}
+
+/// The Cellar of brew, or of the Intel brew under Rosetta, if brew can tell.
+fn brew_cellar(rosetta: bool) -> Option<String> {
+    let output = if rosetta { run_command("arch", &rosetta_args(&["--cellar"])) } else { run_command(brew_command(), &["--cellar"]) }.ok()?;
+    let cellar = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    debug!("Cellar of brew: {}", cellar);
+    Some(cellar)
+}
+
+/// The name of the formula's package in the registry, where `@` can't appear.
+fn image_name(brew_name: &str) -> String {
+    brew_name.replace('@', "/").replace('+', "x")
+}
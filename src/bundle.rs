@@ -0,0 +1,92 @@
+//! Bundles of a resolved formula file with its metadata and, optionally, its bottle, so the
+//! formula can be installed on machines without internet access.
+//!
+//! A bundle is a tar archive of `bundle.json`, the formula file and the bottle under `bottle/`.
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use log::debug;
+use tempfile::TempDir;
+
+use crate::error::{BrewverError, Result};
+use crate::formula::{Formula, FormulaFile};
+use crate::interrupt;
+
+const MANIFEST: &str = "bundle.json";
+
+/// What a bundle contains, stored as `bundle.json`.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Manifest {
+    formula: Formula,
+    file: String,
+    bottle: Option<String>,
+}
+
+/// A bundle unpacked into a temporary directory, removed when dropped.
+pub struct Bundle {
+    dir: TempDir,
+    manifest: Manifest,
+}
+
+impl Drop for Bundle {
+    fn drop(&mut self) {
+        interrupt::remove_temp_dir(self.dir.path());
+    }
+}
+
+impl Bundle {
+    /// Writes a bundle of the formula file, and the bottle if one is given, to `output`.
+    pub fn write(formula: &Formula, file: &FormulaFile, bottle: Option<&Path>, output: &Path) -> Result<()> {
+        let file_name = format!("{}.rb", formula.name);
+        let bottle_name = bottle.and_then(|bottle| bottle.file_name()).map(|name| name.to_string_lossy().into_owned());
+        let manifest = Manifest {
+            formula: formula.clone(),
+            file: file_name.clone(),
+            bottle: bottle_name.clone(),
+        };
+
+        let mut archive = tar::Builder::new(File::create(output)?);
+        let manifest = serde_json::to_vec_pretty(&manifest)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs());
+        header.set_cksum();
+        archive.append_data(&mut header, MANIFEST, manifest.as_slice())?;
+        archive.append_path_with_name(file.path(), &file_name)?;
+        if let (Some(bottle), Some(name)) = (bottle, bottle_name) {
+            archive.append_path_with_name(bottle, Path::new("bottle").join(name))?;
+        }
+        archive.finish()?;
+        Ok(())
+    }
+
+    /// Unpacks a bundle written by [`Bundle::write`].
+    pub fn open(path: &Path) -> Result<Self> {
+        let dir = interrupt::temp_dir()?;
+        tar::Archive::new(File::open(path)?).unpack(dir.path())?;
+        debug!("Unpacked {:?} to {:?}", path, dir.path());
+
+        let manifest = std::fs::read_to_string(dir.path().join(MANIFEST))
+            .map_err(|_| BrewverError::NotABundle(path.to_path_buf()))?;
+        let manifest = serde_json::from_str(&manifest)?;
+        Ok(Self { dir, manifest })
+    }
+
+    /// The formula the bundle was made for.
+    pub fn formula(&self) -> &Formula {
+        &self.manifest.formula
+    }
+
+    /// A copy of the bundled formula file.
+    pub fn formula_file(&self) -> Result<FormulaFile> {
+        let contents = std::fs::read_to_string(self.dir.path().join(&self.manifest.file))?;
+        FormulaFile::new(&self.manifest.formula.name, &contents)
+    }
+
+    /// The bundled bottle, if the bundle has one.
+    pub fn bottle(&self) -> Option<PathBuf> {
+        self.manifest.bottle.as_ref().map(|name| self.dir.path().join("bottle").join(name))
+    }
+}
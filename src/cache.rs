@@ -0,0 +1,252 @@
+//! An on-disk cache of resolved commits, downloaded formula files and bottles.
+//!
+//! They are immutable once found: the bottle commit of a version doesn't change, neither does
+//! the formula file at a commit, and bottles are stored by their checksum, so entries never
+//! expire. Commit history pages do change, so they are kept with their ETag and revalidated on
+//! the next request.
+//!
+//! Several processes can share the cache, such as the jobs of a CI matrix: each entry is a file
+//! of its own, written to a temporary file and renamed into place, so a reader sees either the
+//! old entry or the complete new one, never a partial write.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use log::debug;
+
+use crate::client::Response;
+use crate::error::Result;
+use crate::stats;
+
+/// A cache directory, by default `$XDG_CACHE_HOME/brewver` or `~/.cache/brewver`.
+pub struct Cache {
+    dir: PathBuf,
+    policy: CachePolicy,
+}
+
+/// When resolved commits, formula files and bottles are taken from the cache rather than the
+/// network.
+#[derive(clap_derive::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum CachePolicy {
+    /// Always go to the network, still storing what it returns in the cache
+    NetworkOnly,
+    /// Never go to the network, as with --offline
+    CacheOnly,
+    /// Take what the cache has, and go to the network for the rest
+    #[default]
+    CacheThenNetwork,
+    /// Go to the network, and take what the cache has when the network fails
+    NetworkThenCache,
+}
+
+/// How much is cached, from [`Cache::summary`].
+#[derive(Clone, Debug, Default)]
+pub struct CacheSummary {
+    /// The cached commits, formula files, bottles and responses.
+    pub entries: usize,
+    pub bytes: u64,
+    /// When the oldest and the newest entry were written, none without entries.
+    pub oldest: Option<SystemTime>,
+    pub newest: Option<SystemTime>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CachedCommit {
+    commit: String,
+    path: String,
+}
+
+impl Cache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into(), policy: CachePolicy::default() }
+    }
+
+    /// Sets when entries are taken from the cache. [`CachePolicy::CacheOnly`] also needs a client
+    /// built with [`crate::ClientBuilder::offline`] not to go to the network on a miss.
+    pub fn policy(mut self, policy: CachePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Whether entries are looked up before going to the network.
+    pub(crate) fn reads_first(&self) -> bool {
+        matches!(self.policy, CachePolicy::CacheOnly | CachePolicy::CacheThenNetwork)
+    }
+
+    /// Whether entries are looked up when the network fails.
+    pub(crate) fn reads_on_failure(&self) -> bool {
+        self.policy == CachePolicy::NetworkThenCache
+    }
+
+    /// The cache in the default directory, if the home directory is known.
+    pub fn open_default() -> Option<Self> {
+        let dir = match std::env::var_os("XDG_CACHE_HOME").filter(|dir| !dir.is_empty()) {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(std::env::var_os("HOME")?).join(".cache"),
+        };
+        Some(Self::new(dir.join("brewver")))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Counts the entries of the cache and their size by scanning its directory, leaving out the
+    /// temporary files of writes in progress.
+    pub fn summary(&self) -> CacheSummary {
+        let mut summary = CacheSummary::default();
+        let mut dirs = vec![self.dir.clone()];
+        while let Some(dir) = dirs.pop() {
+            for path in entries(&dir) {
+                let Ok(metadata) = std::fs::metadata(&path) else {
+                    continue;
+                };
+                if metadata.is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+                if path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.')) {
+                    continue;
+                }
+                summary.entries += 1;
+                summary.bytes += metadata.len();
+                if let Ok(modified) = metadata.modified() {
+                    summary.oldest = Some(summary.oldest.map_or(modified, |oldest| oldest.min(modified)));
+                    summary.newest = Some(summary.newest.map_or(modified, |newest| newest.max(modified)));
+                }
+            }
+        }
+        summary
+    }
+
+    /// Removes every cached entry.
+    pub fn clear(&self) -> std::io::Result<()> {
+        match std::fs::remove_dir_all(&self.dir) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Removes the commits and formula files cached for a formula, returning how many were
+    /// removed. Its bottles are kept, as they are stored by checksum alone.
+    pub fn forget_formula(&self, name: &str) -> std::io::Result<usize> {
+        let mut removed = 0;
+        for repo in entries(&self.dir.join("commits")) {
+            let versions = entries(&repo.join(key(name)));
+            if !versions.is_empty() {
+                removed += versions.len();
+                std::fs::remove_dir_all(repo.join(key(name)))?;
+            }
+        }
+        // Formula files are stored by their path, such as `Formula_n_node.rb`.
+        let file_name = format!("{}.rb", key(name));
+        for commit in entries(&self.dir.join("formulas")).iter().flat_map(|repo| entries(repo)) {
+            for file in entries(&commit) {
+                let stored = file.file_name().unwrap_or_default().to_string_lossy().into_owned();
+                if stored == file_name || stored.ends_with(&format!("_{}", file_name)) {
+                    std::fs::remove_file(file)?;
+                    removed += 1;
+                }
+            }
+        }
+        Ok(removed)
+    }
+
+    /// The commit and formula path a version was resolved to before, under the key of the lookup,
+    /// such as `1.2.3` or `1.2.3~bump`.
+    pub(crate) fn commit(&self, repo: &str, name: &str, version: &str) -> Option<(String, String)> {
+        let path = self.commit_path(repo, name, version);
+        let cached: CachedCommit = serde_json::from_str(&std::fs::read_to_string(&path).ok()?).ok()?;
+        debug!("Cached commit: {:?}", path);
+        stats::count_cache_hit();
+        Some((cached.commit, cached.path))
+    }
+
+    pub(crate) fn store_commit(&self, repo: &str, name: &str, version: &str, commit: &str, path: &str) -> Result<()> {
+        let cached = CachedCommit { commit: commit.to_string(), path: path.to_string() };
+        write(&self.commit_path(repo, name, version), serde_json::to_string(&cached)?.as_bytes())
+    }
+
+    /// A formula file downloaded before.
+    pub(crate) fn formula_file(&self, repo: &str, commit: &str, path: &str) -> Option<String> {
+        let path = self.formula_file_path(repo, commit, path);
+        let contents = std::fs::read_to_string(&path).ok()?;
+        debug!("Cached formula file: {:?}", path);
+        stats::count_cache_hit();
+        Some(contents)
+    }
+
+    pub(crate) fn store_formula_file(&self, repo: &str, commit: &str, path: &str, contents: &str) -> Result<()> {
+        write(&self.formula_file_path(repo, commit, path), contents.as_bytes())
+    }
+
+    /// A bottle downloaded before, by the SHA-256 checksum its formula file declares.
+    pub(crate) fn bottle(&self, sha256: &str) -> Option<PathBuf> {
+        let path = self.bottle_path(sha256);
+        path.is_file().then(|| {
+            debug!("Cached bottle: {:?}", path);
+            stats::count_cache_hit();
+            path
+        })
+    }
+
+    /// Copies a downloaded bottle into the cache, through a temporary file so that a bottle that
+    /// is cut short never takes the place of a complete one.
+    pub(crate) fn store_bottle(&self, sha256: &str, bottle: &Path) -> Result<()> {
+        let path = self.bottle_path(sha256);
+        let dir = path.parent().unwrap_or(&self.dir);
+        std::fs::create_dir_all(dir)?;
+        let partial = tempfile::NamedTempFile::new_in(dir)?;
+        std::fs::copy(bottle, partial.path())?;
+        partial.persist(&path).map_err(|e| e.error)?;
+        Ok(())
+    }
+
+    /// A response received before, to revalidate with its ETag.
+    pub(crate) fn response(&self, url: &str) -> Option<Response> {
+        let path = self.response_path(url);
+        let cached = serde_json::from_str(&std::fs::read_to_string(&path).ok()?).ok()?;
+        debug!("Cached response: {:?}", path);
+        Some(cached)
+    }
+
+    pub(crate) fn store_response(&self, response: &Response) -> Result<()> {
+        write(&self.response_path(&response.url), serde_json::to_string(response)?.as_bytes())
+    }
+
+    fn commit_path(&self, repo: &str, name: &str, version: &str) -> PathBuf {
+        self.dir.join("commits").join(key(repo)).join(key(name)).join(format!("{}.json", key(version)))
+    }
+
+    fn response_path(&self, url: &str) -> PathBuf {
+        let url = url.trim_start_matches("https://").trim_start_matches("http://");
+        self.dir.join("responses").join(format!("{}.json", key(url)))
+    }
+
+    fn bottle_path(&self, sha256: &str) -> PathBuf {
+        self.dir.join("bottles").join(format!("{}.tar.gz", key(sha256)))
+    }
+
+    fn formula_file_path(&self, repo: &str, commit: &str, path: &str) -> PathBuf {
+        self.dir.join("formulas").join(key(repo)).join(key(commit)).join(key(path.trim_start_matches('/')))
+    }
+}
+
+/// Turns a repository, name or path into a single file name.
+fn key(part: &str) -> String {
+    part.replace('/', "_")
+}
+
+/// The entries of a directory, none if it doesn't exist.
+fn entries(dir: &Path) -> Vec<PathBuf> {
+    std::fs::read_dir(dir).into_iter().flatten().filter_map(|entry| entry.ok().map(|entry| entry.path())).collect()
+}
+
+fn write(path: &Path, contents: &[u8]) -> Result<()> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    std::fs::create_dir_all(dir)?;
+    let mut partial = tempfile::NamedTempFile::new_in(dir)?;
+    partial.write_all(contents)?;
+    partial.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
@@ -0,0 +1,88 @@
+//! Defaults for the command line options, read from `$XDG_CONFIG_HOME/brewver/config.toml`
+//! (`~/.config/brewver/config.toml` by default).
+//!
+//! Each setting becomes the default value of the option of the same name, so flags given on the
+//! command line still win.
+
+use std::path::PathBuf;
+
+/// The settings of a config file. Every one of them is optional.
+#[derive(serde::Deserialize, serde::Serialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Config {
+    pub(crate) github_token: Option<String>,
+    pub(crate) resolver: Option<String>,
+    pub(crate) resolver_chain: Option<Vec<String>>,
+    pub(crate) source: Option<String>,
+    pub(crate) tap: Option<String>,
+    pub(crate) fallback_tap: Option<String>,
+    pub(crate) retries: Option<u32>,
+    pub(crate) cache_dir: Option<PathBuf>,
+    pub(crate) cache_policy: Option<String>,
+    pub(crate) pin: Option<bool>,
+    pub(crate) output: Option<String>,
+    pub(crate) timeout: Option<u64>,
+    pub(crate) connect_timeout: Option<u64>,
+    pub(crate) proxy: Option<String>,
+    pub(crate) github_api_url: Option<String>,
+    pub(crate) raw_url: Option<String>,
+    pub(crate) prefix: Option<PathBuf>,
+    pub(crate) pre_hook: Option<String>,
+    pub(crate) post_hook: Option<String>,
+    pub(crate) expand_env_in_hooks: Option<bool>,
+}
+
+impl Config {
+    /// The path of the config file, if the home directory is known.
+    pub(crate) fn path() -> Option<PathBuf> {
+        let dir = match std::env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+        };
+        Some(dir.join("brewver").join("config.toml"))
+    }
+
+    /// Reads the config file, or returns an empty config when there is none.
+    pub(crate) fn load() -> Result<Self, String> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+        toml::from_str(&contents).map_err(|e| format!("Invalid config file {}: {}", path.display(), e))
+    }
+
+    /// Makes the settings the default values of their options.
+    pub(crate) fn apply(&self, mut command: clap::Command) -> clap::Command {
+        let defaults = [
+            ("backend", self.resolver.clone()),
+            ("resolver_chain", self.resolver_chain.as_ref().map(|chain| chain.join(","))),
+            ("source", self.source.clone()),
+            ("tap", self.tap.clone()),
+            ("fallback_tap", self.fallback_tap.clone()),
+            ("retries", self.retries.map(|n| n.to_string())),
+            ("cache_dir", self.cache_dir.as_ref().map(|dir| dir.display().to_string())),
+            ("cache_policy", self.cache_policy.clone()),
+            ("pin", self.pin.map(|pin| pin.to_string())),
+            ("output", self.output.clone()),
+            ("timeout", self.timeout.map(|secs| secs.to_string())),
+            ("connect_timeout", self.connect_timeout.map(|secs| secs.to_string())),
+            ("proxy", self.proxy.clone()),
+            ("github_api_url", self.github_api_url.clone()),
+            ("raw_url", self.raw_url.clone()),
+            ("prefix", self.prefix.as_ref().map(|prefix| prefix.display().to_string())),
+            ("pre_hook", self.pre_hook.clone()),
+            ("post_hook", self.post_hook.clone()),
+            ("expand_env_in_hooks", self.expand_env_in_hooks.map(|expand| expand.to_string())),
+        ];
+        for (id, value) in defaults {
+            if let Some(value) = value {
+                command = command.mut_arg(id, |arg| arg.default_value(value));
+            }
+        }
+        command
+    }
+}
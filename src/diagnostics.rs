@@ -0,0 +1,79 @@
+//! Diagnostic reports of failed runs, written by `--report` to attach to bug reports at brewver
+//! or Homebrew.
+
+use std::path::Path;
+use std::sync::Mutex;
+
+use brewver::{Formula, Installer, RequestRecord};
+
+/// The formulas resolved so far in the run.
+static RESOLVED: Mutex<Vec<Resolved>> = Mutex::new(Vec::new());
+
+/// A formula version and where its formula file was found.
+#[derive(serde::Serialize, Clone)]
+struct Resolved {
+    formula: String,
+    version: String,
+    repo: String,
+    commit: String,
+    url: String,
+}
+
+/// Keeps the commit and URL a formula was resolved to for the report.
+pub(crate) fn note_resolved(formula: &Formula) {
+    RESOLVED.lock().unwrap_or_else(|e| e.into_inner()).push(Resolved {
+        formula: formula.brew_name.clone(),
+        version: formula.version.clone(),
+        repo: formula.repo.clone(),
+        commit: formula.commit.clone(),
+        url: formula.url.clone(),
+    });
+}
+
+/// The report of a failed run.
+#[derive(serde::Serialize)]
+struct Report<'a> {
+    brewver: &'static str,
+    command_line: Vec<String>,
+    os: &'static str,
+    arch: &'static str,
+    brew_version: Option<String>,
+    /// The output of `brew config`.
+    brew_config: Option<String>,
+    errors: &'a [String],
+    exit_code: u8,
+    resolved: Vec<Resolved>,
+    requests: Vec<RequestRecord>,
+    /// What brew printed to stderr in the commands that failed.
+    brew_output: &'a [&'a str],
+}
+
+/// Writes the report of a run that failed with the errors, replacing the GitHub token wherever
+/// it shows up, such as in the output of brew.
+pub(crate) fn write_report(path: &Path, errors: &[String], exit_code: u8, brew_output: &[&str], token: Option<&str>) -> std::io::Result<()> {
+    let brew_config = std::process::Command::new(brewver::brew_command())
+        .arg("config")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).into_owned())
+        .filter(|config| !config.is_empty());
+    let report = Report {
+        brewver: env!("CARGO_PKG_VERSION"),
+        command_line: std::env::args().skip(1).collect(),
+        os: std::env::consts::OS,
+        arch: std::env::consts::ARCH,
+        brew_version: Installer::new().brew_version().ok(),
+        brew_config,
+        errors,
+        exit_code,
+        resolved: RESOLVED.lock().unwrap_or_else(|e| e.into_inner()).clone(),
+        requests: brewver::recorded_requests(),
+        brew_output,
+    };
+    let mut json = serde_json::to_string_pretty(&report)?;
+    if let Some(token) = token.filter(|token| !token.is_empty()) {
+        json = json.replace(token, "********");
+    }
+    std::fs::write(path, json)
+}
@@ -0,0 +1,263 @@
+//! The errors brewver reports.
+
+use std::path::PathBuf;
+
+pub type Result<T> = std::result::Result<T, BrewverError>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum BrewverError {
+    #[error("No bottle commit for {name}@{version} in {repo} (searched {}). Run `brewver versions {name}` to list the available versions", candidates.join(", "))]
+    CommitNotFound { name: String, version: String, repo: String, candidates: Vec<String> },
+
+    #[error("{name} was not found at {git_ref} in {repo} (searched {})", candidates.join(", "))]
+    FileNotFound { name: String, git_ref: String, repo: String, candidates: Vec<String> },
+
+    #[error("{0:?} has no formula name")]
+    EmptyName(String),
+
+    #[error("{repo} has never had a formula or cask named {name}{}", did_you_mean(suggestions))]
+    UnknownFormula { name: String, repo: String, suggestions: Vec<String> },
+
+    #[error("The Homebrew API only has {name} {current}, not {version}. Use --resolver commits to search the commit history")]
+    NotInBrewApi { name: String, version: String, current: String },
+
+    #[error("No versions of {name} were found in its commit history")]
+    VersionsNotFound { name: String },
+
+    #[error("No version of {name} in its commit history matches {pattern}. Run `brewver versions {name}` to list the available versions")]
+    NoMatchingVersion { name: String, pattern: String },
+
+    #[error("Only {found} versions of {name} were found in its commit history, too few to go {n_back} back")]
+    NotEnoughVersions { name: String, found: usize, n_back: usize },
+
+    #[error("{message}")]
+    RateLimited { reset_at: u64, message: String },
+
+    #[error("Only {remaining} GitHub API requests remain (need {required}), the limit resets in {reset_in}s. Set GITHUB_TOKEN to raise the limit or try again later")]
+    RateLimitTooLow { remaining: u64, required: u64, reset_in: u64 },
+
+    #[error("Only {remaining} GitHub API requests are left, but searching the history of {path} can take {needed} more, and the limit resets in {reset_in}s. Use --source git to search the local clone of the tap (`brew tap --force homebrew/core` creates it), or set GITHUB_TOKEN to raise the limit")]
+    RateLimitInsufficient { path: String, remaining: u64, needed: u64, reset_in: u64 },
+
+    #[error("Reached the limit of {0} requests set by --max-total-requests")]
+    RequestLimit(u32),
+
+    #[error("Request failed: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("{url} can't be fetched offline; the commit and formula file have to be in the cache or the local clone of the tap")]
+    Offline { url: String },
+
+    #[error("Invalid proxy {url}: {source}")]
+    InvalidProxy { url: String, source: reqwest::Error },
+
+    #[error("{url} rejected the GitHub token as bad credentials, it may have expired or been revoked")]
+    BadCredentials { url: String },
+
+    #[error("The GitHub token can't read {url}: {message}. Give it read access to the contents and metadata of the repository, or the repo scope for a private one")]
+    TokenLacksAccess { url: String, message: String },
+
+    #[error("{name} at {commit} has no head in its formula file, so it can't be built with --HEAD")]
+    NoHead { name: String, commit: String },
+
+    #[error("The cache is turned off by --no-cache or a trace, or there is no HOME or XDG_CACHE_HOME to keep it in")]
+    NoCache,
+
+    #[error("The GitHub token is a JWT of a GitHub App, which can't read repositories. Use an installation token of the app instead")]
+    AppJwt,
+
+    #[error("GitHub API returned HTTP {status} for {url}: {message}")]
+    GitHubApi { url: String, status: u16, message: String },
+
+    #[error("{url} returned HTTP {status}")]
+    HttpStatus { url: String, status: u16 },
+
+    #[error("Unexpected response from {url}: {reason}")]
+    UnexpectedResponse { url: String, reason: String },
+
+    #[error("No recorded response for {url}")]
+    NotRecorded { url: String },
+
+    #[error("{url} is not a {kind} file")]
+    NotAFormulaFile { url: String, kind: &'static str },
+
+    #[error("The formula file of {name} at {commit} is for version {found}, not {version}. Use --no-version-check to install it anyway")]
+    VersionMismatch { name: String, version: String, found: String, commit: String },
+
+    #[error("Signature verification failed: {0}")]
+    Signature(#[from] minisign_verify::Error),
+
+    #[error("`{command}` failed ({status}): {stderr}")]
+    BrewFailed { command: String, status: String, code: Option<i32>, stderr: String },
+
+    #[error("`{command}` was stopped after running for longer than {}s, the install timeout", timeout.as_secs())]
+    BrewTimedOut { command: String, timeout: std::time::Duration },
+
+    #[error("Failed to run `{command}`: {source}")]
+    CommandNotRun { command: String, source: std::io::Error },
+
+    #[error("Only {available} MB are free in {path:?}, but installing {name} takes about {needed} MB. Free up space first, or pass --no-space-check")]
+    InsufficientSpace { name: String, path: PathBuf, available: u64, needed: u64 },
+
+    #[error("The hook `{command}` can't be run, as {reason}")]
+    InvalidHook { command: String, reason: String },
+
+    #[error("The {hook} hook `{command}` failed ({status})")]
+    HookFailed { hook: String, command: String, status: String },
+
+    #[error("{name} is not known to brew")]
+    NotKnownToBrew { name: String },
+
+    #[error("{name}@{version} is not the stable version known to brew ({stable})")]
+    NotStableVersion { name: String, version: String, stable: String },
+
+    #[error("{tap} is not cloned locally at {}, run `brew tap --force {tap}` to clone it or pass --auto-fetch", path.display())]
+    TapNotCloned { tap: String, path: PathBuf },
+
+    #[error("{tap} at {} is a shallow clone without the older commits, run `git -C {} fetch --unshallow` to fetch them or pass --auto-fetch", path.display(), path.display())]
+    ShallowClone { tap: String, path: PathBuf },
+
+    #[error("{} is out of date for {}, run brewver sync without --locked to update it", path.display(), names.join(", "))]
+    LockfileOutdated { path: PathBuf, names: Vec<String> },
+
+    #[error("The formula file at {url} has checksum {found}, not {expected} as locked")]
+    ChecksumMismatch { url: String, expected: String, found: String },
+
+    #[error("Another brewver instance is running, it holds the lock {}. Use --lock-timeout to wait for it", .0.display())]
+    AlreadyRunning(PathBuf),
+
+    #[error("The formula file downloaded from {url} has blob SHA {found}, but GitHub lists {expected} for it")]
+    BlobMismatch { url: String, expected: String, found: String },
+
+    #[error("The bottle downloaded from {url} has checksum {found}, not {expected} as its formula file declares")]
+    BottleChecksumMismatch { url: String, expected: String, found: String },
+
+    #[error("The source archive downloaded from {url} has checksum {found}, not {expected} as its formula file declares")]
+    SourceChecksumMismatch { url: String, expected: String, found: String },
+
+    #[error("{name} {version} is deprecated, disabled or known to be vulnerable, pass --allow-deprecated to install it anyway")]
+    Discouraged { name: String, version: String },
+
+    #[error("{name} {version} is affected by {}", ids.join(", "))]
+    Vulnerable { name: String, version: String, ids: Vec<String> },
+
+    #[error("{} depend on {name}, which replacing it can break. Use --keep-current to install the version next to it, or --force-remove to replace it anyway", dependents.join(", "))]
+    HasDependents { name: String, dependents: Vec<String> },
+
+    #[error("Linking {name} would overwrite {} files other formulas linked, such as {}. Use --link-overwrite to replace them", files.len(), files.first().map(String::as_str).unwrap_or_default())]
+    LinkConflict { name: String, files: Vec<String> },
+
+    #[error("Release {tag} of brewver has no {asset} binary for this platform")]
+    NoReleaseAsset { tag: String, asset: String },
+
+    #[error("The release binary downloaded from {url} has checksum {found}, not {expected} as published")]
+    ReleaseChecksumMismatch { url: String, expected: String, found: String },
+
+    #[error("brewver needs Homebrew {required} or later, but brew is {found}. Run `brew update` to update it")]
+    BrewTooOld { found: String, required: String },
+
+    #[error("brew reported installing {name} {version}, but lists {} as installed", if installed.is_empty() { "no version".to_string() } else { installed.join(", ") })]
+    NotInstalled { name: String, version: String, installed: Vec<String> },
+
+    #[error("{0} There is no terminal to confirm in, pass --yes to go ahead")]
+    ConfirmationRequired(String),
+
+    #[error("Not confirmed, nothing was changed")]
+    NotConfirmed,
+
+    #[error("brew is not installed or not on PATH. Install Homebrew from https://brew.sh, or pass --prefix to run the one of another installation")]
+    BrewNotInstalled,
+
+    #[error("There is no Homebrew installation at {}, {}/bin/brew is missing", .0.display(), .0.display())]
+    NoBrewAtPrefix(PathBuf),
+
+    #[error("No .brew-versions file found in {} or its parents", .0.display())]
+    NoVersionFile(PathBuf),
+
+    #[error("{name} was not installed because its dependency {dependency} failed")]
+    DependencyFailed { name: String, dependency: String },
+
+    #[error("{} already exists, use --force to replace it", .0.display())]
+    ManifestExists(PathBuf),
+
+    #[error("Invalid manifest {}: {reason}", path.display())]
+    InvalidManifest { path: PathBuf, reason: String },
+
+    #[error("{} is not a brewver bundle", .0.display())]
+    NotABundle(PathBuf),
+
+    #[error("{} is not valid UTF-8", .0.display())]
+    InvalidPath(PathBuf),
+
+    #[error("The version of {name} is unknown")]
+    NoVersion { name: String },
+
+    #[error("No keg of {name} {version} is installed")]
+    KegNotInstalled { name: String, version: String },
+
+    #[error("No previous version of {name} was recorded")]
+    NoPreviousVersion { name: String },
+
+    #[error("The cache directory is unknown, set HOME or XDG_CACHE_HOME")]
+    NoCacheDir,
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+impl BrewverError {
+    /// Whether the request failed to get an answer, rather than getting one that says no, so
+    /// [`crate::CachePolicy::NetworkThenCache`] falls back to the cache.
+    pub(crate) fn is_network(&self) -> bool {
+        match self {
+            BrewverError::Http(_) | BrewverError::Offline { .. } | BrewverError::RateLimited { .. } | BrewverError::RequestLimit(_) => true,
+            BrewverError::HttpStatus { status, .. } => *status >= 500,
+            _ => false,
+        }
+    }
+
+    /// The exit code for errors that mean the same thing whichever stage they happen in.
+    pub fn exit_code(&self) -> Option<u8> {
+        match self {
+            BrewverError::CommitNotFound { .. }
+            | BrewverError::FileNotFound { .. }
+            | BrewverError::UnknownFormula { .. }
+            | BrewverError::EmptyName(_)
+            | BrewverError::VersionsNotFound { .. }
+            | BrewverError::NotEnoughVersions { .. }
+            | BrewverError::NoMatchingVersion { .. }
+            | BrewverError::NoHead { .. }
+            | BrewverError::NotInBrewApi { .. }
+            | BrewverError::NotStableVersion { .. } => Some(3),
+            BrewverError::HttpStatus { .. }
+            | BrewverError::NotAFormulaFile { .. }
+            | BrewverError::VersionMismatch { .. }
+            | BrewverError::ChecksumMismatch { .. }
+            | BrewverError::BlobMismatch { .. }
+            | BrewverError::BottleChecksumMismatch { .. }
+            | BrewverError::SourceChecksumMismatch { .. }
+            | BrewverError::ReleaseChecksumMismatch { .. }
+            | BrewverError::Signature(_) => Some(4),
+            BrewverError::BrewFailed { .. } | BrewverError::BrewTimedOut { .. } => Some(5),
+            BrewverError::RateLimited { .. }
+            | BrewverError::RateLimitTooLow { .. }
+            | BrewverError::RateLimitInsufficient { .. }
+            | BrewverError::RequestLimit(_) => Some(6),
+            BrewverError::Discouraged { .. } | BrewverError::Vulnerable { .. } => Some(8),
+            _ => None,
+        }
+    }
+}
+
+/// Names close to a mistyped one, as `, did you mean `wget`?`.
+fn did_you_mean(suggestions: &[String]) -> String {
+    let quoted: Vec<String> = suggestions.iter().map(|name| format!("`{}`", name)).collect();
+    match quoted.split_last() {
+        None => String::new(),
+        Some((last, [])) => format!(", did you mean {}?", last),
+        Some((last, rest)) => format!(", did you mean {} or {}?", rest.join(", "), last),
+    }
+}
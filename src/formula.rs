@@ -0,0 +1,516 @@
+//! A resolved formula version and its downloaded formula file.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use log::{debug, info, warn};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use tempfile::TempDir;
+
+use crate::client::GithubClient;
+use crate::interrupt;
+use crate::error::{BrewverError, Result};
+use crate::git::LocalTap;
+use crate::github::format_gh_api_contents_url;
+
+/// A formula version pinned to the commit (or tag) whose formula file installs it.
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub struct Formula {
+    /// The name the formula was looked up by in the repository.
+    pub name: String,
+    /// The name brew knows the formula by, used for every brew command.
+    pub brew_name: String,
+    /// The requested version, empty when the formula was resolved from a tag.
+    pub version: String,
+    /// The GitHub repository the formula file was found in.
+    pub repo: String,
+    /// The path of the formula file within the repository.
+    pub path: String,
+    /// The commit hash or tag the formula file is taken from.
+    pub commit: String,
+    /// The raw URL of the formula file.
+    pub url: String,
+    /// Whether this is a cask rather than a formula.
+    pub cask: bool,
+}
+
+impl fmt::Debug for Formula {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Formula: {}\nVersion: {}\nCommit: {}\nURL: {}", self.name, self.version, self.commit, self.url)
+    }
+}
+
+impl Formula {
+    /// A formula installed from a file of its own, such as one written by `--save`, rather than
+    /// one resolved from a repository. Its version is read from the file.
+    pub fn local(file: &FormulaFile, source: &str, cask: bool) -> Result<Formula> {
+        check_contents(source, &file.contents()?, cask)?;
+        let name = file.path().file_stem().and_then(|stem| stem.to_str()).unwrap_or_default().to_string();
+        let version = file.info()?.version.ok_or_else(|| BrewverError::NoVersion { name: name.clone() })?;
+        Ok(Formula {
+            brew_name: name.clone(),
+            name,
+            version,
+            repo: String::new(),
+            path: String::new(),
+            commit: String::new(),
+            url: source.to_string(),
+            cask,
+        })
+    }
+
+    /// Downloads the formula file into a temporary directory that lives as long as the returned file.
+    pub fn download(&self, client: &dyn GithubClient) -> Result<FormulaFile> {
+        let cached = client.cache().filter(|cache| cache.reads_first()).and_then(|cache| cache.formula_file(&self.repo, &self.commit, &self.path));
+        let contents = match cached {
+            Some(contents) => contents,
+            None if client.is_offline() => self.read_from_clone()?,
+            None => {
+                let contents = match self.fetch(client) {
+                    Err(e) if e.is_network() => {
+                        let fallback = client.cache().filter(|cache| cache.reads_on_failure());
+                        return fallback.and_then(|cache| cache.formula_file(&self.repo, &self.commit, &self.path))
+                            .ok_or(e)
+                            .and_then(|contents| FormulaFile::new(&self.name, &contents));
+                    }
+                    contents => contents?,
+                };
+                if let Some(cache) = client.cache() {
+                    if let Err(e) = cache.store_formula_file(&self.repo, &self.commit, &self.path, &contents) {
+                        warn!("Failed to cache the formula file: {}", e);
+                    }
+                }
+                contents
+            }
+        };
+
+        FormulaFile::new(&self.name, &contents)
+    }
+
+    /// Reads the file from the local clone of the repository, for offline runs.
+    fn read_from_clone(&self) -> Result<String> {
+        let contents = LocalTap::open(&self.repo)?.show(&self.commit, &self.path)
+            .ok_or_else(|| BrewverError::Offline { url: self.url.clone() })?;
+        check_contents(&self.url, &contents, self.cask)?;
+        Ok(contents)
+    }
+
+    fn fetch(&self, client: &dyn GithubClient) -> Result<String> {
+        let response = client.download(&self.url)?;
+        if !response.is_success() {
+            return Err(BrewverError::HttpStatus { url: response.url, status: response.status });
+        }
+        check_contents(&response.url, &response.body, self.cask)?;
+        Ok(response.body)
+    }
+
+    /// Checks the downloaded file against the git blob SHA GitHub lists for the path at the commit,
+    /// so a file altered on the way is refused. When GitHub can't be asked, e.g. because of the
+    /// rate limit, the check is skipped with a warning.
+    pub fn verify_blob(&self, client: &dyn GithubClient, file: &FormulaFile) -> Result<()> {
+        if self.commit.is_empty() || client.is_offline() {
+            return Ok(());
+        }
+        let url = format_gh_api_contents_url(client.api_url(), &self.repo, &self.path, &self.commit);
+        let expected = match client.get(&url).and_then(|response| response.api_json()) {
+            Ok(json) => json.get("sha").and_then(|sha| sha.as_str()).map(String::from),
+            Err(e) => {
+                warn!("Failed to look up the blob SHA of {}, it is not verified: {}", self.path, e);
+                return Ok(());
+            }
+        };
+        let Some(expected) = expected else {
+            warn!("GitHub listed no blob SHA for {}, it is not verified", self.path);
+            return Ok(());
+        };
+        let found = file.blob_sha()?;
+        if found != expected {
+            return Err(BrewverError::BlobMismatch { url: self.url.clone(), expected, found });
+        }
+        debug!("Blob SHA of {} verified: {}", self.path, found);
+        Ok(())
+    }
+
+    /// Reasons to think twice before installing the version: upstream deprecated or disabled it
+    /// in the formula file, or it belongs to a release line known to be vulnerable.
+    pub fn cautions(&self, file: &FormulaFile) -> Result<Vec<String>> {
+        let info = file.info()?;
+        let version = if self.version.is_empty() { info.version.clone().unwrap_or_default() } else { self.version.clone() };
+        let mut cautions = Vec::new();
+        for (status, retirement) in [("disabled", &info.disabled), ("deprecated", &info.deprecated)] {
+            if let Some(retirement) = retirement {
+                cautions.push(format!("{} {} is {} upstream{}", self.brew_name, version, status, retirement));
+            }
+        }
+        let vulnerable = VULNERABLE_RELEASES.iter()
+            .find(|(name, line, _)| *name == self.brew_name && version.starts_with(line));
+        if let Some((_, _, advisory)) = vulnerable {
+            cautions.push(format!("{} {} has known vulnerabilities: {}", self.brew_name, version, advisory));
+        }
+        Ok(cautions)
+    }
+
+    /// Checks that the file declares the version the formula was resolved for, or has it in the
+    /// name of its source archive as [`crate::FormulaResolver::match_upstream_url`] resolves it.
+    /// A commit message alone doesn't guarantee it, e.g. for a revert commit.
+    pub fn check_version(&self, file: &FormulaFile) -> Result<()> {
+        if self.version.is_empty() {
+            return Ok(());
+        }
+        let without_revision = match self.version.rsplit_once('_') {
+            Some((version, revision)) if revision.chars().all(|c| c.is_ascii_digit()) => version,
+            _ => &self.version,
+        };
+        let upstream = upstream_version(&file.contents()?);
+        match file.info()?.version {
+            Some(found) if found == self.version || found == without_revision => Ok(()),
+            Some(_) if upstream.as_deref() == Some(without_revision) => Ok(()),
+            Some(found) => Err(BrewverError::VersionMismatch { name: self.name.clone(), version: self.version.clone(), found, commit: self.commit.clone() }),
+            None => {
+                warn!("The version of {} could not be read from its formula file", self.name);
+                Ok(())
+            }
+        }
+    }
+
+    /// A Brewfile line for the formula, noting where its formula file came from.
+    pub fn brewfile_entry(&self) -> String {
+        if self.cask {
+            return format!("cask \"{}\" # {}@{}", self.brew_name, self.repo, self.commit);
+        }
+        let spec = if self.version.is_empty() { self.brew_name.clone() } else { format!("{}@{}", self.brew_name, self.version) };
+        format!("brew \"{}\" # {}@{}", spec, self.repo, self.commit)
+    }
+}
+
+/// The metadata a formula file declares.
+#[derive(serde::Serialize)]
+pub struct FormulaInfo {
+    /// The version declared in the file, or else the one in the name of the source archive.
+    pub version: Option<String>,
+    pub desc: Option<String>,
+    pub homepage: Option<String>,
+    /// The URL of the source archive.
+    pub url: Option<String>,
+    /// The checksum of the source archive.
+    pub sha256: Option<String>,
+    pub license: Option<String>,
+    pub dependencies: Vec<String>,
+    pub bottles: Vec<Bottle>,
+    /// The `root_url` of the `bottle do` block, for bottles published elsewhere than homebrew-core's.
+    pub bottle_root_url: Option<String>,
+    /// The `rebuild` of the bottles, which ends up in their file names.
+    pub bottle_rebuild: Option<u32>,
+    /// The `deprecate!` statement, if upstream deprecated the formula.
+    pub deprecated: Option<Retirement>,
+    /// The `disable!` statement, if upstream disabled the formula.
+    pub disabled: Option<Retirement>,
+    pub caveats: Option<String>,
+    /// Why the formula is keg-only, such as `versioned_formula` or `provided_by_macos`, if it is.
+    pub keg_only: Option<String>,
+    /// The repository a `--HEAD` build is made from, if the formula can be built from it.
+    pub head: Option<String>,
+}
+
+/// A `depends_on` line naming a formula.
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct Dependency {
+    pub name: String,
+    /// `build`, `test`, `optional` or `recommended`, none for plain runtime dependencies.
+    pub tags: Vec<String>,
+}
+
+/// A `deprecate!` or `disable!` statement.
+#[derive(serde::Serialize)]
+pub struct Retirement {
+    pub date: Option<String>,
+    /// Why, either a reason such as `unmaintained` or a sentence.
+    pub because: Option<String>,
+    /// The formula to use instead.
+    pub replacement: Option<String>,
+}
+
+impl fmt::Display for Retirement {
+    /// Formats the details as a suffix, e.g. ` since 2023-10-24 (unmaintained, use foo instead)`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(date) = &self.date {
+            write!(f, " since {}", date)?;
+        }
+        let details: Vec<String> = self.because.iter().cloned()
+            .chain(self.replacement.iter().map(|replacement| format!("use {} instead", replacement)))
+            .collect();
+        if !details.is_empty() {
+            write!(f, " ({})", details.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Release lines that reached their end of life with vulnerabilities left unfixed, by formula.
+const VULNERABLE_RELEASES: &[(&str, &str, &str)] = &[
+    ("openssl", "1.0.", "OpenSSL 1.0.2 reached its end of life in 2019"),
+    ("openssl@1.0", "1.0.", "OpenSSL 1.0.2 reached its end of life in 2019"),
+    ("openssl", "1.1.", "OpenSSL 1.1.1 reached its end of life in September 2023"),
+    ("openssl@1.1", "1.1.", "OpenSSL 1.1.1 reached its end of life in September 2023"),
+    ("python", "2.", "Python 2 reached its end of life in January 2020"),
+    ("python@2", "2.", "Python 2 reached its end of life in January 2020"),
+    ("curl", "7.", "curl 7 is no longer maintained, and many of its releases have known CVEs"),
+];
+
+/// A prebuilt bottle and the platform it is for.
+#[derive(serde::Serialize)]
+pub struct Bottle {
+    pub tag: String,
+    pub sha256: String,
+    /// The Cellar the bottle was built for, `:any` or `:any_skip_relocation` when it pours into
+    /// any, or a path such as `/home/linuxbrew/.linuxbrew/Cellar` when it only pours there.
+    pub cellar: Option<String>,
+}
+
+/// A downloaded formula file, removed together with its directory when dropped.
+pub struct FormulaFile {
+    _dir: TempDir,
+    path: PathBuf,
+}
+
+impl Drop for FormulaFile {
+    fn drop(&mut self) {
+        interrupt::remove_temp_dir(self._dir.path());
+    }
+}
+
+impl FormulaFile {
+    /// Writes the contents to `<name>.rb` in a new temporary directory.
+    pub fn new(name: &str, contents: &str) -> Result<Self> {
+        let dir = interrupt::temp_dir()?;
+        let path = dir.path().join(format!("{}.rb", name));
+        debug!("Temp File: {:?}", &path);
+        std::fs::write(&path, contents)?;
+        Ok(FormulaFile { _dir: dir, path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn contents(&self) -> Result<String> {
+        Ok(std::fs::read_to_string(&self.path)?)
+    }
+
+    /// The SHA-256 checksum of the file, in hex.
+    pub fn sha256(&self) -> Result<String> {
+        sha256_file(&self.path)
+    }
+
+    /// The SHA git names the file's contents by, in hex.
+    pub fn blob_sha(&self) -> Result<String> {
+        let content = std::fs::read(&self.path)?;
+        let mut hasher = Sha1::new();
+        hasher.update(format!("blob {}\0", content.len()));
+        hasher.update(&content);
+        Ok(hex(&hasher.finalize()))
+    }
+
+    /// The formulas named in `depends_on` lines, apart from test-only dependencies.
+    pub fn dependencies(&self) -> Result<Vec<String>> {
+        Ok(self.dependency_specs()?
+            .into_iter()
+            .filter(|dependency| !dependency.tags.iter().any(|tag| tag == "test"))
+            .map(|dependency| dependency.name)
+            .collect())
+    }
+
+    /// Every formula named in a `depends_on` line, with the tags saying when it is needed.
+    pub fn dependency_specs(&self) -> Result<Vec<Dependency>> {
+        Ok(self.contents()?
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("depends_on \""))
+            .filter_map(|rest| rest.split_once('"'))
+            .map(|(name, options)| Dependency {
+                name: name.to_string(),
+                tags: options.split(':').skip(1)
+                    .filter_map(|tag| tag.split(|c: char| !c.is_ascii_alphanumeric() && c != '_').next())
+                    .filter(|tag| !tag.is_empty())
+                    .map(String::from)
+                    .collect(),
+            })
+            .collect())
+    }
+
+    /// Reads the metadata of the formula from the file.
+    pub fn info(&self) -> Result<FormulaInfo> {
+        let contents = self.contents()?;
+        let field = |key: &str| top_level_lines(&contents).find_map(|line| line.strip_prefix(key)?.strip_prefix(' ').map(unquote));
+
+        let url = field("url").or_else(|| contents.lines().find_map(|line| line.trim().strip_prefix("url ").map(unquote)));
+        Ok(FormulaInfo {
+            version: declared_version(&contents),
+            desc: field("desc"),
+            homepage: field("homepage"),
+            url,
+            sha256: field("sha256"),
+            license: field("license"),
+            dependencies: self.dependencies()?,
+            bottles: block(&contents, "bottle do").iter().filter_map(|line| bottle(line)).collect(),
+            bottle_root_url: block_field(&contents, "bottle do", "root_url").map(|value| unquote(value.split(',').next().unwrap_or(value))),
+            bottle_rebuild: block_field(&contents, "bottle do", "rebuild").and_then(|value| value.parse().ok()),
+            deprecated: retirement(&contents, "deprecate!"),
+            disabled: retirement(&contents, "disable!"),
+            caveats: caveats(&contents),
+            keg_only: field("keg_only").map(|reason| reason.trim_start_matches(':').to_string()),
+            head: field("head").filter(|head| head != "do")
+                .or_else(|| block_field(&contents, "head do", "url").map(unquote))
+                .map(|head| unquote(head.split(',').next().unwrap_or(&head))),
+        })
+    }
+
+    /// Downloads the source archive the file declares to `dest` and checks it against its
+    /// checksum, returning whether there was one to download. Sources cloned from a repository, or
+    /// declared without a checksum, are left to brew.
+    pub fn download_source(&self, client: &dyn GithubClient, dest: &Path) -> Result<bool> {
+        let info = self.info()?;
+        let (Some(url), Some(expected)) = (info.url.filter(|url| url.starts_with("https://") && !url.contains('"')), info.sha256) else {
+            return Ok(false);
+        };
+        // The archive only takes its place once it is complete and checked, as brew would find
+        // a partial one there.
+        let dir = dest.parent().unwrap_or(Path::new("."));
+        std::fs::create_dir_all(dir)?;
+        let partial = tempfile::NamedTempFile::new_in(dir)?;
+        client.download_file(&url, partial.path())?;
+        let found = sha256_file(partial.path())?;
+        if found != expected {
+            return Err(BrewverError::SourceChecksumMismatch { url, expected, found });
+        }
+        partial.persist(dest).map_err(|e| e.error)?;
+        debug!("Downloaded {} to {:?}", url, dest);
+        Ok(true)
+    }
+
+    /// Checks the file against a minisign signature and public key.
+    pub fn verify_signature(&self, signature: &Path, public_key: &Path) -> Result<()> {
+        let public_key = minisign_verify::PublicKey::from_file(public_key)?;
+        let signature = minisign_verify::Signature::from_file(signature)?;
+        let content = std::fs::read(&self.path)?;
+        public_key.verify(&content, &signature, false)?;
+        info!("Signature verified: {}", signature.trusted_comment());
+        Ok(())
+    }
+}
+
+/// The version a formula file declares, in its `version` or else the name of its source archive.
+pub(crate) fn declared_version(contents: &str) -> Option<String> {
+    let field = |key: &str| top_level_lines(contents).find_map(|line| line.strip_prefix(key)?.strip_prefix(' ').map(unquote));
+    field("version").or_else(|| upstream_version(contents))
+}
+
+/// The version in the name of the source archive a formula file declares, which is upstream's
+/// version even when the `version` of the formula differs from it.
+pub(crate) fn upstream_version(contents: &str) -> Option<String> {
+    let field = |key: &str| top_level_lines(contents).find_map(|line| line.strip_prefix(key)?.strip_prefix(' ').map(unquote));
+    let url = field("url").or_else(|| contents.lines().find_map(|line| line.trim().strip_prefix("url ").map(unquote)))?;
+    url_version(&url)
+}
+
+/// The SHA-256 checksum of a file, in hex.
+pub(crate) fn sha256_file(path: &Path) -> Result<String> {
+    Ok(hex(&Sha256::digest(std::fs::read(path)?)))
+}
+
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Checks that a downloaded file is a formula, or a cask, rather than an error page.
+pub(crate) fn check_contents(url: &str, contents: &str, cask: bool) -> Result<()> {
+    let is_formula_file = if cask { contents.contains("cask \"") } else { contents.contains("< Formula") };
+    if !is_formula_file {
+        return Err(BrewverError::NotAFormulaFile { url: url.to_string(), kind: if cask { "cask" } else { "formula" } });
+    }
+    Ok(())
+}
+
+/// The version in the name of a source archive, e.g. `1.6` for `.../jq-1.6.tar.gz` or `1.2.3` for
+/// `.../archive/refs/tags/v1.2.3.tar.gz`, the way brew guesses it.
+fn url_version(url: &str) -> Option<String> {
+    let file_name = url.rsplit('/').next()?;
+    let stem = [".tar.gz", ".tar.xz", ".tar.bz2", ".tgz", ".zip"].iter()
+        .find_map(|extension| file_name.strip_suffix(extension))
+        .unwrap_or(file_name);
+    let version = stem.rsplit(['-', '_']).next()?.trim_start_matches('v');
+    version.starts_with(|c: char| c.is_ascii_digit()).then(|| version.to_string())
+}
+
+/// The statements of the class body, leaving out nested blocks such as `bottle`, `resource` or `stable`.
+fn top_level_lines(contents: &str) -> impl Iterator<Item = &str> {
+    contents.lines()
+        .filter(|line| line.starts_with("  ") && !line.starts_with("   "))
+        .map(str::trim)
+}
+
+/// The lines inside a top-level `<opening>` ... `end` block.
+fn block<'c>(contents: &'c str, opening: &str) -> Vec<&'c str> {
+    contents.lines()
+        .skip_while(|line| line.trim_end() != format!("  {}", opening))
+        .skip(1)
+        .take_while(|line| line.trim_end() != "  end")
+        .collect()
+}
+
+/// The value of a `<key> <value>` statement inside a top-level block.
+fn block_field<'c>(contents: &'c str, opening: &str, key: &str) -> Option<&'c str> {
+    block(contents, opening).into_iter().find_map(|line| line.trim().strip_prefix(key)?.strip_prefix(' ').map(str::trim))
+}
+
+/// Parses a `sha256 cellar: :any, arm64_sonoma: "<hash>"` bottle line, or the older
+/// `sha256 "<hash>" => :mojave` form.
+fn bottle(line: &str) -> Option<Bottle> {
+    let rest = line.trim().strip_prefix("sha256 ")?;
+    let (tag, sha256) = match rest.split_once(" => :") {
+        Some((sha256, tag)) => (tag, sha256),
+        None => {
+            let (before, sha256) = rest.rsplit_once(": ")?;
+            (before.rsplit([' ', ',']).next()?, sha256)
+        }
+    };
+    let cellar = rest.split_once("cellar: ")
+        .and_then(|(_, cellar)| cellar.split(',').next())
+        .map(|cellar| cellar.trim().trim_matches('"').to_string());
+    Some(Bottle { tag: tag.trim().to_string(), sha256: unquote(sha256), cellar })
+}
+
+/// Parses a `deprecate! date: "2023-10-24", because: :unmaintained, replacement: "foo"` statement.
+fn retirement(contents: &str, keyword: &str) -> Option<Retirement> {
+    let arguments = top_level_lines(contents).find_map(|line| line.strip_prefix(keyword))?;
+    let argument = |key: &str| {
+        let value = arguments.split_once(&format!("{}: ", key))?.1;
+        match value.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').map(|(value, _)| value.to_string()),
+            None => value.strip_prefix(':')
+                .map(|symbol| symbol.split(|c: char| !c.is_ascii_alphanumeric() && c != '_').next().unwrap_or_default().replace('_', " "))
+                .filter(|symbol| !symbol.is_empty()),
+        }
+    };
+    Some(Retirement {
+        date: argument("date"),
+        because: argument("because"),
+        replacement: argument("replacement").or_else(|| argument("replacement_formula")),
+    })
+}
+
+/// The text of the `caveats` method, without the heredoc markers.
+fn caveats(contents: &str) -> Option<String> {
+    let lines: Vec<&str> = block(contents, "def caveats").into_iter()
+        .map(str::trim)
+        .filter(|line| !line.starts_with("<<") && *line != "EOS")
+        .collect();
+    (!lines.is_empty()).then(|| lines.join("\n"))
+}
+
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+    match value.strip_prefix('"').and_then(|value| value.strip_suffix('"')) {
+        Some(value) if !value.contains('"') => value.to_string(),
+        _ => value.to_string(),
+    }
+}
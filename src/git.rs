@@ -0,0 +1,119 @@
+//! Reads formula history from the local clone of a tap, for when the GitHub API can't be used.
+
+use std::path::{Path, PathBuf};
+use log::debug;
+
+use crate::github::repo_tap;
+use crate::error::{BrewverError, Result};
+use crate::installer::{brew_command, brew_installed, run_command};
+
+/// A tap cloned by brew, found through `brew --repository`.
+pub(crate) struct LocalTap {
+    path: PathBuf,
+}
+
+/// A commit touching a formula file, with the subject line of its message.
+pub(crate) struct GitCommit {
+    pub(crate) sha: String,
+    pub(crate) date: String,
+    pub(crate) subject: String,
+}
+
+impl LocalTap {
+    /// Finds the local clone of a GitHub repository such as `Homebrew/homebrew-core`.
+    pub(crate) fn open(repo: &str) -> Result<Self> {
+        if !brew_installed() {
+            return Err(BrewverError::BrewNotInstalled);
+        }
+        let tap = repo_tap(repo);
+        let output = run_command(brew_command(), &["--repository", &tap])?;
+        let path = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+        if !path.join(".git").exists() {
+            return Err(BrewverError::TapNotCloned { tap, path });
+        }
+        debug!("Local clone of {}: {:?}", tap, path);
+        Ok(Self { path })
+    }
+
+    /// Clones a tap such as `homebrew/core` with brew, which clones it in full.
+    pub(crate) fn clone(tap: &str) -> Result<()> {
+        run_command(brew_command(), &["tap", "--force", tap])?;
+        Ok(())
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the clone lacks older commits, as a `git clone --depth` leaves it.
+    pub(crate) fn is_shallow(&self) -> bool {
+        self.git(&["rev-parse", "--is-shallow-repository"])
+            .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).trim() == "true")
+    }
+
+    /// Fetches the commits a shallow clone lacks.
+    pub(crate) fn unshallow(&self) -> Result<()> {
+        self.git(&["fetch", "--unshallow"])?;
+        Ok(())
+    }
+
+    /// Lists the commits touching a file, newest first.
+    pub(crate) fn commits(&self, file_path: &str) -> Result<Vec<GitCommit>> {
+        self.log(&[], file_path)
+    }
+
+    /// The newest commit touching a file that was committed before the given date.
+    pub(crate) fn last_commit_until(&self, file_path: &str, until: &str) -> Result<Option<GitCommit>> {
+        Ok(self.log(&["-1", &format!("--until={}", until)], file_path)?.into_iter().next())
+    }
+
+    fn log(&self, options: &[&str], file_path: &str) -> Result<Vec<GitCommit>> {
+        let args: Vec<&str> = ["log", "--format=%H%x1f%cI%x1f%s"].into_iter()
+            .chain(options.iter().copied())
+            .chain(["--", file_path.trim_start_matches('/')])
+            .collect();
+        let output = self.git(&args)?;
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.splitn(3, '\u{1f}');
+                Some(GitCommit {
+                    sha: fields.next()?.to_string(),
+                    date: fields.next()?.to_string(),
+                    subject: fields.next()?.to_string(),
+                })
+            })
+            .collect())
+    }
+
+    /// The committer date of a commit or tag.
+    pub(crate) fn commit_date(&self, git_ref: &str) -> Result<String> {
+        let output = self.git(&["log", "-1", "--format=%cI", git_ref])?;
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    /// The contents of a file at a commit or tag, if it exists there.
+    pub(crate) fn show(&self, git_ref: &str, file_path: &str) -> Option<String> {
+        let object = format!("{}:{}", git_ref, file_path.trim_start_matches('/'));
+        let output = self.git(&["show", &object]).ok()?;
+        Some(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// Checks whether the file exists at a commit or tag.
+    pub(crate) fn has_file(&self, git_ref: &str, file_path: &str) -> bool {
+        let object = format!("{}:{}", git_ref, file_path.trim_start_matches('/'));
+        self.git(&["cat-file", "-e", &object]).is_ok()
+    }
+
+    /// Lists the files of the default branch, with paths relative to the root.
+    pub(crate) fn files(&self) -> Result<Vec<String>> {
+        let output = self.git(&["ls-tree", "-r", "--name-only", "HEAD"])?;
+        Ok(String::from_utf8_lossy(&output.stdout).lines().map(String::from).collect())
+    }
+
+    fn git(&self, args: &[&str]) -> Result<std::process::Output> {
+        let path = self.path.to_str().ok_or_else(|| BrewverError::InvalidPath(self.path.clone()))?;
+        let args: Vec<&str> = ["-C", path].into_iter().chain(args.iter().copied()).collect();
+        run_command("git", &args)
+    }
+}
@@ -0,0 +1,89 @@
+//! GitHub API and raw file URLs for the repositories brewver reads formulas from, and the
+//! Homebrew API URLs of formulas and casks.
+
+/// The GitHub API, unless the client is configured to use a GitHub Enterprise server or a mirror.
+pub const DEFAULT_API_URL: &str = "https://api.github.com";
+
+/// Where raw files are served from by default.
+pub const DEFAULT_RAW_URL: &str = "https://raw.githubusercontent.com";
+
+pub(crate) fn format_gh_api_commits_url(api: &str, repo: &str, file_path: &str) -> String {
+    format!("{}/repos/{}/commits?path={}&per_page=100", api, repo, file_path)
+}
+
+pub(crate) fn format_gh_api_commit_url(api: &str, repo: &str, commit: &str) -> String {
+    format!("{}/repos/{}/commits/{}", api, repo, commit)
+}
+
+pub(crate) fn format_gh_api_commits_until_url(api: &str, repo: &str, file_path: &str, until: &str) -> String {
+    format!("{}/repos/{}/commits?path={}&until={}&per_page=1", api, repo, file_path, until)
+}
+
+pub(crate) fn format_gh_api_rate_limit_url(api: &str) -> String {
+    format!("{}/rate_limit", api)
+}
+
+pub(crate) fn format_gh_api_repo_url(api: &str, repo: &str) -> String {
+    format!("{}/repos/{}", api, repo)
+}
+
+pub(crate) fn format_gh_api_tree_url(api: &str, repo: &str) -> String {
+    format!("{}/repos/{}/git/trees/HEAD?recursive=1", api, repo)
+}
+
+pub(crate) fn format_gh_api_compare_url(api: &str, repo: &str, base: &str, head: &str) -> String {
+    format!("{}/repos/{}/compare/{}...{}", api, repo, base, head)
+}
+
+pub(crate) fn format_gh_api_contents_url(api: &str, repo: &str, file_path: &str, git_ref: &str) -> String {
+    format!("{}/repos/{}/contents{}?ref={}", api, repo, file_path, git_ref)
+}
+
+/// The GraphQL endpoint next to the REST API: `/graphql` on GitHub, `/api/graphql` on GitHub Enterprise.
+pub(crate) fn format_gh_graphql_url(api: &str) -> String {
+    match api.strip_suffix("/v3") {
+        Some(base) => format!("{}/graphql", base),
+        None => format!("{}/graphql", api),
+    }
+}
+
+/// A GraphQL query for a page of the default branch history of each path, given with the cursor
+/// to continue after. The histories are aliased `h0`, `h1`, ... in the order of the paths.
+pub(crate) fn format_gh_graphql_history_query(repo: &str, paths: &[(&str, Option<&str>)]) -> String {
+    let (owner, name) = repo.split_once('/').unwrap_or((repo, ""));
+    let histories: Vec<String> = paths.iter().enumerate().map(|(i, (path, cursor))| {
+        let after = cursor.map(|cursor| format!(", after: {}", serde_json::Value::from(cursor))).unwrap_or_default();
+        format!(
+            "h{}: history(first: 100, path: {}{}) {{ pageInfo {{ hasNextPage endCursor }} nodes {{ oid message committedDate }} }}",
+            i, serde_json::Value::from(path.trim_start_matches('/')), after,
+        )
+    }).collect();
+    format!(
+        "query {{ repository(owner: {}, name: {}) {{ defaultBranchRef {{ target {{ ... on Commit {{ {} }} }} }} }} }}",
+        serde_json::Value::from(owner), serde_json::Value::from(name), histories.join(" "),
+    )
+}
+
+pub(crate) fn format_gh_api_raw_file_url(raw: &str, repo: &str, commit: &str, file_path: &str) -> String {
+    format!("{}/{}/{}{}", raw, repo, commit, file_path)
+}
+
+pub(crate) fn format_brew_api_url(name: &str, cask: bool) -> String {
+    format!("https://formulae.brew.sh/api/{}/{}.json", if cask { "cask" } else { "formula" }, name)
+}
+
+/// Maps a tap name such as `user/repo` to its GitHub repository `user/homebrew-repo`.
+pub fn tap_repo(tap: &str) -> String {
+    match tap.split_once('/') {
+        Some((user, repo)) if !repo.starts_with("homebrew-") => format!("{}/homebrew-{}", user, repo),
+        _ => tap.to_string(),
+    }
+}
+
+/// Maps a GitHub repository such as `user/homebrew-repo` back to its tap name `user/repo`.
+pub(crate) fn repo_tap(repo: &str) -> String {
+    match repo.split_once('/') {
+        Some((user, repo)) => format!("{}/{}", user.to_lowercase(), repo.trim_start_matches("homebrew-")),
+        None => repo.to_string(),
+    }
+}
@@ -0,0 +1,104 @@
+//! Splits the command of a `--pre-hook` or `--post-hook` into the program and its arguments,
+//! which are run without a shell.
+//!
+//! The command is split on unquoted whitespace, much like a POSIX shell would, but nothing else
+//! of the shell applies: there are no pipes, redirections, globs, `~` or command substitution.
+//!
+//! - `'single quotes'` keep everything up to the next `'` as it is.
+//! - `"double quotes"` keep everything up to the next unescaped `"`, where a backslash only
+//!   escapes `"`, `\` and `$` and is kept before any other character.
+//! - Outside of quotes, a backslash keeps the next character as it is, whitespace included.
+//! - Quoted and unquoted parts next to each other make up one argument, and `''` or `""` is an
+//!   empty argument.
+//!
+//! With expansion on, `$NAME` and `${NAME}` outside of single quotes are replaced with the value
+//! of the variable, or with nothing when it isn't set. The value becomes part of the argument it
+//! is in and is never split or expanded again. A `$` that isn't followed by a name is an error
+//! then, unless it is escaped as `\$`. With expansion off, `$` is an ordinary character.
+
+use crate::error::{BrewverError, Result};
+
+/// Splits a hook command into the program and its arguments. With `expand`, `$NAME` and
+/// `${NAME}` are replaced with the value `env` has for the name, or else the one in the
+/// environment of brewver.
+pub fn hook_args(command: &str, expand: bool, env: &[(&str, &str)]) -> Result<Vec<String>> {
+    let invalid = |reason: &str| BrewverError::InvalidHook { command: command.to_string(), reason: reason.to_string() };
+    let lookup = |name: &str| match env.iter().find(|(key, _)| *key == name) {
+        Some((_, value)) => value.to_string(),
+        None => std::env::var(name).unwrap_or_default(),
+    };
+
+    let mut args = Vec::new();
+    let mut arg: Option<String> = None;
+    let mut chars = command.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => args.extend(arg.take()),
+            '\'' => {
+                let arg = arg.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => arg.push(c),
+                        None => return Err(invalid("a single quote isn't closed")),
+                    }
+                }
+            }
+            '"' => {
+                let arg = arg.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$')) => arg.push(c),
+                            Some(c) => {
+                                arg.push('\\');
+                                arg.push(c);
+                            }
+                            None => return Err(invalid("a double quote isn't closed")),
+                        },
+                        Some('$') if expand => arg.push_str(&lookup(&variable(&mut chars).map_err(invalid)?)),
+                        Some(c) => arg.push(c),
+                        None => return Err(invalid("a double quote isn't closed")),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some(c) => arg.get_or_insert_with(String::new).push(c),
+                None => return Err(invalid("it ends with a backslash")),
+            },
+            '$' if expand => {
+                let name = variable(&mut chars).map_err(invalid)?;
+                arg.get_or_insert_with(String::new).push_str(&lookup(&name));
+            }
+            c => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+    args.extend(arg);
+    if args.is_empty() {
+        return Err(invalid("there is no command to run"));
+    }
+    Ok(args)
+}
+
+/// Reads the name of a variable after a `$`, either `NAME` or `{NAME}`.
+fn variable(chars: &mut std::iter::Peekable<std::str::Chars>) -> std::result::Result<String, &'static str> {
+    if chars.next_if_eq(&'{').is_some() {
+        let mut name = String::new();
+        loop {
+            match chars.next() {
+                Some('}') if !name.is_empty() => return Ok(name),
+                Some(c) if c == '_' || c.is_ascii_alphanumeric() => name.push(c),
+                _ => return Err("a ${...} variable isn't a valid name"),
+            }
+        }
+    }
+    let mut name = String::new();
+    while let Some(c) = chars.next_if(|c| *c == '_' || c.is_ascii_alphanumeric()) {
+        name.push(c);
+    }
+    if name.is_empty() {
+        return Err("a $ isn't followed by a variable name; escape it as \\$ to keep it");
+    }
+    Ok(name)
+}
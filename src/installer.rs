@@ -0,0 +1,1505 @@
+//! Installs downloaded formula files with brew.
+
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, OnceLock};
+use std::time::Duration;
+use log::{debug, error, info, warn};
+
+use crate::formula::{Formula, FormulaFile};
+use crate::error::{BrewverError, Result};
+use crate::interrupt;
+use crate::progress::{self, ProgressEvent};
+use crate::resolver::version_key;
+use crate::state::{HistoryEntry, State};
+use crate::stats;
+
+/// The tap `extract` writes versioned formulas to.
+pub const EXTRACT_TAP: &str = "brewver/versions";
+
+/// Runs the brew commands that put a formula version in place of the installed one.
+///
+/// ```no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let client = brewver::Client::new();
+/// # let formula = brewver::FormulaResolver::new(&client).resolve("wget", "1.21.3")?;
+/// let file = formula.download(&client)?;
+/// brewver::Installer::new().unlink_after(true).install(&formula, &file)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Installer {
+    brew: Rc<dyn BrewRunner>,
+    link_only: bool,
+    link_mode: LinkMode,
+    unlink_after: bool,
+    pin: bool,
+    extract: bool,
+    keep_current: bool,
+    keep_kegs: bool,
+    install_args: Vec<String>,
+    audit: bool,
+    force_remove: bool,
+    restart_services: bool,
+    check_space: bool,
+    verify: bool,
+    quiet: bool,
+    state: Option<State>,
+    confirm: Option<Confirm>,
+}
+
+/// How an installed formula that brew left unlinked is linked, see [`Installer::link_mode`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum LinkMode {
+    /// Leave linking to brew, warning about the files of other formulas that kept it unlinked
+    #[default]
+    Brew,
+    /// Link it, failing with the files of other formulas that are in the way
+    Link,
+    /// Link it with `--overwrite`, replacing the files of other formulas that are in the way
+    Overwrite,
+}
+
+/// Asks whether to go ahead with the step it describes, see [`Installer::confirm`].
+pub type Confirm = Rc<dyn Fn(&str) -> Result<()>>;
+
+/// A keg installed next to the linked version of a formula.
+pub struct Keg {
+    /// The name brew knows the keg by, `<name>@<version>` unless it is a keg of the formula itself.
+    pub name: String,
+    pub prefix: PathBuf,
+}
+
+/// Runs the brew commands of an [`Installer`]. [`SystemBrew`] runs the brew on PATH, and
+/// `MockBrew`, with the `mock` feature, stands in for it in tests.
+pub trait BrewRunner {
+    /// Runs a brew command that only reads, returning its stdout.
+    fn output(&self, args: &[&str]) -> Result<String>;
+
+    /// Runs a brew command that changes the installation, logging its output line by line as it
+    /// runs unless quiet.
+    fn run(&self, args: &[&str], quiet: bool) -> Result<()>;
+}
+
+/// Runs the brew found by [`brew_command`].
+pub struct SystemBrew;
+
+impl BrewRunner for SystemBrew {
+    fn output(&self, args: &[&str]) -> Result<String> {
+        let output = timed(|| run_command_with(brew_command(), args, brew_env()))?;
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    fn run(&self, args: &[&str], quiet: bool) -> Result<()> {
+        timed(|| run_changing(brew_command(), args, quiet))
+    }
+}
+
+/// Runs a brew command, counting its time in the [`crate::run_stats`].
+fn timed<T>(command: impl FnOnce() -> T) -> T {
+    let started = std::time::Instant::now();
+    let result = command();
+    stats::add_brew_time(started.elapsed());
+    result
+}
+
+/// A release of Homebrew, as `brew --version` prints it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct HomebrewVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+/// The oldest brew with the commands brewver runs: `brew install --cask` and `info --json=v2`
+/// came with 2.6.0, as did the removal of `brew switch`, which `switch` makes up for.
+pub const MIN_BREW_VERSION: HomebrewVersion = HomebrewVersion { major: 2, minor: 6, patch: 0 };
+
+/// Homebrew loads formulas from its JSON API by default since 4.0.0.
+const API_BREW_VERSION: HomebrewVersion = HomebrewVersion { major: 4, minor: 0, patch: 0 };
+
+impl HomebrewVersion {
+    /// Parses a version line like `Homebrew 4.2.0` or `Homebrew 4.2.0-21-g3a8ef4e`.
+    pub fn parse(line: &str) -> Option<Self> {
+        let version = line.trim().strip_prefix("Homebrew ")?.split(['-', ' ']).next()?;
+        let mut parts = version.split('.').map(|part| part.parse::<u32>());
+        let major = parts.next()?.ok()?;
+        let minor = parts.next().unwrap_or(Ok(0)).ok()?;
+        let patch = parts.next().unwrap_or(Ok(0)).ok()?;
+        Some(HomebrewVersion { major, minor, patch })
+    }
+}
+
+impl std::fmt::Display for HomebrewVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// The environment brew commands run with, so that brew installs the formula file it is handed
+/// as it is. Variables the user set are left alone.
+fn brew_env() -> &'static [(&'static str, &'static str)] {
+    static ENV: OnceLock<Vec<(&'static str, &'static str)>> = OnceLock::new();
+    ENV.get_or_init(|| {
+        let mut env = vec![
+            // Updating the taps first can't change a formula file given as a path, and takes long.
+            ("HOMEBREW_NO_AUTO_UPDATE", "1"),
+            // Otherwise brew upgrades the installed dependents of the formula, which can bring
+            // back the versions brewver replaced.
+            ("HOMEBREW_NO_INSTALLED_DEPENDENTS_CHECK", "1"),
+        ];
+        // The JSON API has no old versions, and brew can prefer it over the formula file when
+        // resolving the formula by name.
+        let output = run_command(brew_command(), &["--version"]).ok();
+        let version = output.and_then(|output| HomebrewVersion::parse(String::from_utf8_lossy(&output.stdout).lines().next()?));
+        if version.is_some_and(|version| version >= API_BREW_VERSION) {
+            env.push(("HOMEBREW_NO_INSTALL_FROM_API", "1"));
+        }
+        env.retain(|(name, _)| std::env::var_os(name).is_none());
+        debug!("Running brew with {:?}", env);
+        env
+    })
+}
+
+/// Where the Intel brew of a Mac with Apple Silicon lives.
+pub const ROSETTA_BREW: &str = "/usr/local/bin/brew";
+
+/// Runs the Intel brew in `/usr/local` under Rosetta, for old versions that only have bottles
+/// for x86_64 Macs.
+pub struct RosettaBrew;
+
+impl BrewRunner for RosettaBrew {
+    fn output(&self, args: &[&str]) -> Result<String> {
+        let output = timed(|| run_command_with("arch", &rosetta_args(args), brew_env()))?;
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    fn run(&self, args: &[&str], quiet: bool) -> Result<()> {
+        timed(|| run_changing("arch", &rosetta_args(args), quiet))
+    }
+}
+
+static INSTALL_TIMEOUT: OnceLock<Duration> = OnceLock::new();
+
+/// Stops the brew commands that change the installation once they run longer than the timeout,
+/// such as an install that turned into a build of a large formula from source. The command and
+/// the processes it started are stopped, and the command fails with
+/// [`BrewverError::BrewTimedOut`]. Such commands can't read from the terminal then.
+pub fn set_install_timeout(timeout: Duration) {
+    let _ = INSTALL_TIMEOUT.set(timeout);
+}
+
+/// Runs a brew command that changes the installation, within the install timeout.
+fn run_changing(command: &str, args: &[&str], quiet: bool) -> Result<()> {
+    let timeout = INSTALL_TIMEOUT.get().copied();
+    if quiet {
+        let output = run_command_within(command, args, brew_env(), timeout)?;
+        for line in String::from_utf8_lossy(&output.stdout).lines().chain(String::from_utf8_lossy(&output.stderr).lines()) {
+            progress::report(|| ProgressEvent::InstallOutputLine { line: line.to_string() });
+        }
+        Ok(())
+    } else {
+        stream_command(command, args, brew_env(), timeout)
+    }
+}
+
+pub(crate) fn rosetta_args<'a>(args: &[&'a str]) -> Vec<&'a str> {
+    ["-x86_64", ROSETTA_BREW].into_iter().chain(args.iter().copied()).collect()
+}
+
+/// Where Homebrew installs itself on Apple Silicon, Intel Macs and Linux, in order.
+const BREW_LOCATIONS: &[&str] = &["/opt/homebrew/bin/brew", "/usr/local/bin/brew", "/home/linuxbrew/.linuxbrew/bin/brew"];
+
+static BREW: OnceLock<String> = OnceLock::new();
+
+/// Runs the brew of the Homebrew installation at the prefix, such as a second one kept for old
+/// toolchains in `/opt/homebrew-alt`, instead of the default one. Has no effect once brew ran.
+pub fn set_brew_prefix(prefix: &Path) -> Result<()> {
+    let brew = prefix.join("bin").join("brew");
+    if !brew.is_file() {
+        return Err(BrewverError::NoBrewAtPrefix(prefix.to_path_buf()));
+    }
+    let _ = BREW.set(brew.display().to_string());
+    Ok(())
+}
+
+/// The brew to run: the one set by [`set_brew_prefix`], else the one in `HOMEBREW_PREFIX`, else
+/// the one on PATH, or else one in a default location of Homebrew, since CI images with Homebrew
+/// on Linux often leave `/home/linuxbrew/.linuxbrew/bin` off PATH.
+pub fn brew_command() -> &'static str {
+    BREW.get_or_init(|| {
+        let prefixed = std::env::var_os("HOMEBREW_PREFIX")
+            .filter(|prefix| !prefix.is_empty())
+            .map(|prefix| PathBuf::from(prefix).join("bin").join("brew"))
+            .filter(|brew| brew.is_file());
+        if let Some(brew) = prefixed {
+            return brew.display().to_string();
+        }
+        if brew_on_path() {
+            return "brew".to_string();
+        }
+        let home = std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".linuxbrew/bin/brew"));
+        let found = BREW_LOCATIONS.iter().map(PathBuf::from).chain(home).find(|brew| brew.is_file());
+        match found {
+            Some(brew) => {
+                debug!("brew is not on PATH, using {:?}", brew);
+                brew.display().to_string()
+            }
+            None => "brew".to_string(),
+        }
+    })
+}
+
+fn brew_on_path() -> bool {
+    std::env::var_os("PATH").is_some_and(|path| std::env::split_paths(&path).any(|dir| dir.join("brew").is_file()))
+}
+
+/// Whether there is a brew to run at all, for commands that only read formula files to go on
+/// without one and the others to fail up front with [`BrewverError::BrewNotInstalled`].
+pub fn brew_installed() -> bool {
+    match brew_command() {
+        "brew" => brew_on_path(),
+        brew => Path::new(brew).is_file(),
+    }
+}
+
+impl Default for Installer {
+    fn default() -> Self {
+        Self {
+            brew: Rc::new(SystemBrew),
+            link_only: false,
+            link_mode: LinkMode::Brew,
+            unlink_after: false,
+            pin: false,
+            extract: false,
+            keep_current: false,
+            keep_kegs: false,
+            install_args: Vec::new(),
+            audit: false,
+            force_remove: false,
+            restart_services: false,
+            check_space: false,
+            verify: false,
+            quiet: false,
+            state: None,
+            confirm: None,
+        }
+    }
+}
+
+impl Installer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks before replacing the installed version or overwriting links, with a summary of what
+    /// will happen. An error it returns stops the install before brew changes anything.
+    pub fn confirm(mut self, confirm: impl Fn(&str) -> Result<()> + 'static) -> Self {
+        self.confirm = Some(Rc::new(confirm));
+        self
+    }
+
+    /// Runs brew through another [`BrewRunner`] than [`SystemBrew`].
+    pub fn brew(mut self, brew: impl BrewRunner + 'static) -> Self {
+        self.brew = Rc::new(brew);
+        self
+    }
+
+    /// Links the formula with `--overwrite` after installing it.
+    pub fn link_only(mut self, link_only: bool) -> Self {
+        self.link_only = link_only;
+        self
+    }
+
+    /// Links the installed formula when brew left it unlinked, e.g. because files of another
+    /// formula are in the way. Keg-only formulas stay unlinked. Ignored with
+    /// [`Installer::link_only`] and [`Installer::unlink_after`].
+    pub fn link_mode(mut self, link_mode: LinkMode) -> Self {
+        self.link_mode = link_mode;
+        self
+    }
+
+    /// Leaves the installed formula unlinked.
+    pub fn unlink_after(mut self, unlink_after: bool) -> Self {
+        self.unlink_after = unlink_after;
+        self
+    }
+
+    /// Pins the formula after installing it, so `brew upgrade` leaves the version alone.
+    pub fn pin(mut self, pin: bool) -> Self {
+        self.pin = pin;
+        self
+    }
+
+    /// Writes the formula file as `<name>@<version>` into the [`EXTRACT_TAP`] tap and installs it
+    /// from there, so brew knows the version and neither `brew upgrade` nor `brew reinstall`
+    /// replaces it. The unversioned formula is unlinked but kept.
+    pub fn extract(mut self, extract: bool) -> Self {
+        self.extract = extract;
+        self
+    }
+
+    /// Installs the formula as an additional keg with [`Installer::install_keg`], leaving the
+    /// current version installed and linked, and logs how to switch to the new one.
+    pub fn keep_current(mut self, keep_current: bool) -> Self {
+        self.keep_current = keep_current;
+        self
+    }
+
+    /// Keeps the kegs of the other installed versions instead of removing them once the new one
+    /// is in place, so [`Installer::switch`] can go back to them.
+    pub fn keep_kegs(mut self, keep_kegs: bool) -> Self {
+        self.keep_kegs = keep_kegs;
+        self
+    }
+
+    /// Passes extra arguments such as `--build-from-source` or `--HEAD` to every `brew install`.
+    pub fn install_args(mut self, install_args: Vec<String>) -> Self {
+        self.install_args = install_args;
+        self
+    }
+
+    /// Checks after installing that brew lists the version, which otherwise counts as a failed
+    /// install, and that the binary named after the formula, if it has one, prints it for
+    /// `--version`, warning when it doesn't.
+    pub fn verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    /// Runs `brew audit --strict --online` and `brew style` on formula files before installing
+    /// them, and gives up before touching the installed version if either fails.
+    pub fn audit(mut self, audit: bool) -> Self {
+        self.audit = audit;
+        self
+    }
+
+    /// Replaces a version other installed formulas depend on, instead of failing with
+    /// [`BrewverError::HasDependents`].
+    pub fn force_remove(mut self, force_remove: bool) -> Self {
+        self.force_remove = force_remove;
+        self
+    }
+
+    /// Stops the service of a formula running under `brew services`, such as postgresql or
+    /// redis, before replacing its version, and starts it again once the new version is in place
+    /// or the previous one is relinked.
+    pub fn restart_services(mut self, restart_services: bool) -> Self {
+        self.restart_services = restart_services;
+        self
+    }
+
+    /// Checks before replacing the installed version that the prefix, and the temporary directory
+    /// brew builds in, have room for the install, estimated from the size of the bottle or of the
+    /// source archive in brew's cache, and fails with [`BrewverError::InsufficientSpace`] if not.
+    pub fn check_space(mut self, check_space: bool) -> Self {
+        self.check_space = check_space;
+        self
+    }
+
+    /// Captures the output of brew instead of logging it line by line as it runs.
+    pub fn quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    /// Records the formulas it installs, and the version each install replaces for rolling back
+    /// to it later.
+    pub fn state(mut self, state: Option<State>) -> Self {
+        self.state = state;
+        self
+    }
+
+    /// Swaps the installed formula for the downloaded file. The previous keg is only unlinked
+    /// until the new one is installed, so a failed install can be rolled back by relinking it.
+    /// Casks have no kegs to swap and are reinstalled with `--force`.
+    pub fn install(&self, formula: &Formula, file: &FormulaFile) -> Result<()> {
+        let result = self.install_file(formula, file);
+        progress::report_finished(formula, &result);
+        result
+    }
+
+    fn install_file(&self, formula: &Formula, file: &FormulaFile) -> Result<()> {
+        debug!("Install from File: {:?}", file.path());
+        debug!("Bottle File Content: {}", file.contents()?);
+
+        let formula_file = file.path().to_str().ok_or_else(|| BrewverError::InvalidPath(file.path().to_path_buf()))?;
+        if formula.cask {
+            self.run(&self.install_command(&["--cask", "--force", formula_file]))?;
+            self.record_history(HistoryEntry::new("install", &formula.brew_name).version(&formula.version).commit(&formula.commit));
+            return Ok(());
+        }
+        if self.audit {
+            self.audit_file(formula_file)?;
+        }
+        if self.extract {
+            return self.install_extracted(formula, file);
+        }
+        if self.keep_current {
+            let keg = self.install_keg(formula, file)?;
+            info!("Installed {}@{} to {:?}, next to the current version. To use it, put its bin first on PATH:", formula.brew_name, formula.version, keg.prefix);
+            info!("  export PATH=\"{}:$PATH\"", keg.prefix.join("bin").display());
+            if keg.name != formula.brew_name {
+                info!("or link it in place of the current version:");
+                info!("  brew unlink {} && brew link --force --overwrite {}", formula.brew_name, keg.name);
+            }
+            self.record_history(HistoryEntry::new("install", &keg.name).version(&formula.version).commit(&formula.commit));
+            return Ok(());
+        }
+        self.replace(formula, formula_file)
+    }
+
+    /// Pours a bottle of the formula in place of the installed version, the way
+    /// [`Installer::install`] installs a formula file. Only plain installs can use bottles, so
+    /// casks, [`Installer::extract`] and [`Installer::keep_current`] are ignored.
+    /// The formula file the bottle was found in is what [`Installer::audit`] checks.
+    pub fn install_bottle(&self, formula: &Formula, file: &FormulaFile, bottle: &Path) -> Result<()> {
+        let result = self.pour_bottle(formula, file, bottle);
+        progress::report_finished(formula, &result);
+        result
+    }
+
+    fn pour_bottle(&self, formula: &Formula, file: &FormulaFile, bottle: &Path) -> Result<()> {
+        if self.audit {
+            let formula_file = file.path().to_str().ok_or_else(|| BrewverError::InvalidPath(file.path().to_path_buf()))?;
+            self.audit_file(formula_file)?;
+        }
+        let bottle = bottle.to_str().ok_or_else(|| BrewverError::InvalidPath(bottle.to_path_buf()))?;
+        self.replace(formula, bottle)
+    }
+
+    /// Checks that brew lists the version it just installed, and compares the `--version` of its
+    /// binary.
+    fn verify_installed(&self, formula: &Formula) -> Result<()> {
+        if !self.verify || formula.version.is_empty() {
+            return Ok(());
+        }
+        let installed = self.installed_versions(&formula.brew_name);
+        let Some(keg_version) = installed.iter().find(|version| same_version(version, &formula.version)) else {
+            return Err(BrewverError::NotInstalled { name: formula.brew_name.clone(), version: formula.version.clone(), installed });
+        };
+
+        let binary_name = formula.brew_name.split('@').next().unwrap_or(&formula.brew_name);
+        let cellar = self.brew.output(&["--cellar", &formula.brew_name])?;
+        let binary = PathBuf::from(cellar.trim()).join(keg_version).join("bin").join(binary_name);
+        if !binary.is_file() {
+            return Ok(());
+        }
+        match version_output(&binary) {
+            Some(output) if output.contains(&formula.version) => debug!("{:?} --version printed {}", binary, formula.version),
+            Some(output) => warn!("{} {} is installed, but `{} --version` printed {:?}", formula.brew_name, formula.version, binary_name, output.lines().next().unwrap_or_default()),
+            None => debug!("{:?} --version failed or didn't finish", binary),
+        }
+        Ok(())
+    }
+
+    /// Asks before replacing other installed versions of the formula or overwriting links.
+    fn confirm_replace(&self, formula: &Formula, previous_versions: &[String], service: bool) -> Result<()> {
+        let Some(confirm) = &self.confirm else {
+            return Ok(());
+        };
+        let replaced: Vec<&str> = previous_versions.iter().map(String::as_str).filter(|version| *version != formula.version).collect();
+        let mut steps = Vec::new();
+        if service {
+            steps.push(format!("stop the {} service until it is installed", formula.brew_name));
+        }
+        if !replaced.is_empty() {
+            steps.push(format!("unlink {} {}", formula.brew_name, replaced.join(", ")));
+            if !self.keep_kegs {
+                steps.push(format!("remove the keg of {}", replaced.join(", ")));
+            }
+        }
+        if self.link_only {
+            steps.push(format!("link {} with --overwrite, replacing the files other formulas linked", formula.brew_name));
+        } else if self.link_mode == LinkMode::Overwrite && !self.unlink_after {
+            steps.push(format!("link {} with --overwrite if files other formulas linked are in the way", formula.brew_name));
+        }
+        if steps.is_empty() {
+            return Ok(());
+        }
+        confirm(&format!("Installing {} {} will {}. Continue?", formula.brew_name, formula.version, steps.join(" and ")))
+    }
+
+    fn audit_file(&self, formula_file: &str) -> Result<()> {
+        info!("Auditing {}", formula_file);
+        for command in self.audit_plan(formula_file) {
+            let args: Vec<&str> = command[1..].iter().map(String::as_str).collect();
+            self.run(&args)?;
+        }
+        Ok(())
+    }
+
+    fn audit_plan(&self, formula_file: &str) -> Vec<Vec<String>> {
+        vec![brew(&["audit", "--strict", "--online", formula_file]), brew(&["style", formula_file])]
+    }
+
+    /// Unlinks the installed version, installs the formula file or bottle and removes the kegs of
+    /// the other versions, relinking the installed version if the install fails.
+    fn replace(&self, formula: &Formula, source: &str) -> Result<()> {
+        let previous_versions = self.installed_versions(&formula.brew_name);
+        if !self.force_remove && previous_versions.iter().any(|version| *version != formula.version) {
+            let dependents = self.dependents(&formula.brew_name);
+            if !dependents.is_empty() {
+                return Err(BrewverError::HasDependents { name: formula.brew_name.clone(), dependents });
+            }
+        }
+        let service = self.restart_services && previous_versions.iter().any(|version| *version != formula.version)
+            && self.service_running(&formula.brew_name);
+        if service {
+            warn_data_format(formula, &previous_versions);
+        }
+        self.confirm_replace(formula, &previous_versions, service)?;
+        self.ensure_space(formula, source)?;
+        if service {
+            info!("Stopping the {} service", formula.brew_name);
+            self.run(&["services", "stop", &formula.brew_name])?;
+        }
+        if !previous_versions.is_empty() {
+            self.run(&["unlink", &formula.brew_name])?;
+            interrupt::set_unlinked(Some(&formula.brew_name));
+        }
+
+        let mut link_failed = false;
+        let installed = self.run(&self.install_command(&[source]))
+            .or_else(|e| match e {
+                // brew fails when it installed the keg but couldn't link it, which is up to the link mode.
+                BrewverError::BrewFailed { ref stderr, .. } if stderr.contains("`brew link` step did not complete")
+                    && self.installed_versions(&formula.brew_name).iter().any(|version| same_version(version, &formula.version)) => {
+                    warn!("brew installed {} {}, but failed to link it", formula.brew_name, formula.version);
+                    link_failed = true;
+                    Ok(())
+                }
+                e => Err(e),
+            })
+            .and_then(|()| self.verify_installed(formula));
+        interrupt::set_unlinked(None);
+        if let Err(e) = installed {
+            if !previous_versions.is_empty() {
+                warn!("Relinking the previous version of {}", formula.brew_name);
+                if let Err(link_error) = self.run(&["link", &formula.brew_name]) {
+                    error!("Failed to relink the previous version: {}", link_error);
+                }
+            }
+            if service {
+                self.start_service(&formula.brew_name);
+            }
+            return Err(e);
+        }
+
+        let previous = previous_versions.iter().rfind(|version| **version != formula.version);
+        if let (Some(state), Some(previous)) = (&self.state, previous) {
+            if let Err(e) = state.record_previous_version(&formula.brew_name, previous) {
+                warn!("Failed to record the previous version of {}: {}", formula.brew_name, e);
+            }
+        }
+        let finished = (|| -> Result<()> {
+            if !self.keep_kegs {
+                remove_kegs(self.brew.as_ref(), &formula.brew_name, &formula.version, &previous_versions)?;
+            }
+
+            if self.link_only {
+                self.run(&["link", "--overwrite", &formula.brew_name])?;
+            } else if self.unlink_after {
+                self.run(&["unlink", &formula.brew_name])?;
+            } else if link_failed || self.link_mode != LinkMode::Brew {
+                self.ensure_linked(formula)?;
+            }
+            if self.pin {
+                self.run(&["pin", &formula.brew_name])?;
+            }
+            Ok(())
+        })();
+        if service {
+            self.start_service(&formula.brew_name);
+        }
+        finished?;
+        self.record_managed(&formula.brew_name, formula, previous.map(String::as_str));
+        Ok(())
+    }
+
+    /// Fails when a file system the install writes to has less space free than the bottle, or the
+    /// build from the formula file, is estimated to take. A source archive not in brew's cache yet
+    /// is fetched first, as the build would download it anyway; without a size to estimate from,
+    /// nothing is checked.
+    fn ensure_space(&self, formula: &Formula, source: &str) -> Result<()> {
+        if !self.check_space {
+            return Ok(());
+        }
+        let size = |path: &Path| std::fs::metadata(path).ok().map(|metadata| metadata.len());
+        let prefix = PathBuf::from(self.brew.output(&["--prefix"])?.trim());
+        let needed = if source.ends_with(".rb") {
+            let archive = PathBuf::from(self.brew.output(&["--cache", "--build-from-source", "--formula", source])?.trim());
+            if size(&archive).is_none() {
+                info!("Fetching the source of {} to check the free space", formula.brew_name);
+                if let Err(e) = self.run(&["fetch", "--build-from-source", "--formula", source]) {
+                    debug!("Failed to fetch the source of {}: {}", formula.brew_name, e);
+                }
+            }
+            let Some(archive_size) = size(&archive) else {
+                warn!("The size of the source of {} is unknown, not checking the free space", formula.brew_name);
+                return Ok(());
+            };
+            vec![(prefix, archive_size * BOTTLE_EXPANSION), (brew_temp_dir(), archive_size * BUILD_EXPANSION)]
+        } else {
+            vec![(prefix, size(Path::new(source)).unwrap_or_default() * BOTTLE_EXPANSION)]
+        };
+
+        // The prefix and the temporary directory are often on the same file system.
+        let mut by_mount: Vec<(String, PathBuf, u64, u64)> = Vec::new();
+        for (path, bytes) in needed {
+            let Some((mount, available)) = free_space(&path) else {
+                debug!("Couldn't tell the free space in {:?}", path);
+                continue;
+            };
+            match by_mount.iter_mut().find(|(found, ..)| *found == mount) {
+                Some((_, _, _, total)) => *total += bytes,
+                None => by_mount.push((mount, path, available, bytes)),
+            }
+        }
+        for (_, path, available, needed) in by_mount {
+            debug!("{} MB of {:?} are free, installing {} takes about {} MB", megabytes(available), path, formula.brew_name, megabytes(needed));
+            if available < needed {
+                return Err(BrewverError::InsufficientSpace {
+                    name: formula.brew_name.clone(),
+                    path,
+                    available: megabytes(available),
+                    needed: megabytes(needed),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether `brew services` lists the formula's service as started. When brew can't tell, such
+    /// as without the services command, it is assumed not to run.
+    fn service_running(&self, brew_name: &str) -> bool {
+        let Ok(output) = self.brew.output(&["services", "list", "--json"]) else {
+            return false;
+        };
+        let services: Vec<serde_json::Value> = serde_json::from_str(&output).unwrap_or_default();
+        services.iter().any(|service| {
+            service.get("name").and_then(|name| name.as_str()) == Some(brew_name)
+                && service.get("status").and_then(|status| status.as_str()) == Some("started")
+        })
+    }
+
+    /// Starts the formula's service again, warning when it doesn't start, as the install itself
+    /// is done by then.
+    fn start_service(&self, brew_name: &str) {
+        info!("Starting the {} service again", brew_name);
+        if let Err(e) = self.run(&["services", "start", brew_name]) {
+            warn!("Failed to start the {} service again: {}", brew_name, e);
+            warn!("Start it with `brew services start {}`", brew_name);
+        }
+    }
+
+    /// Links the installed version if brew left it unlinked, as the link mode says. Without files
+    /// of other formulas in the way, an unlinked formula is keg-only and stays unlinked. Unless
+    /// told to link, this is only checked when brew failed to link it.
+    fn ensure_linked(&self, formula: &Formula) -> Result<()> {
+        if self.linked_version(&formula.brew_name).is_some_and(|linked| same_version(&linked, &formula.version)) {
+            return Ok(());
+        }
+        let conflicts = self.link_conflicts(&formula.brew_name);
+        if conflicts.is_empty() {
+            return Ok(());
+        }
+        match self.link_mode {
+            LinkMode::Brew => {
+                warn!("{} is not linked, because these files of other formulas are in the way:", formula.brew_name);
+                for file in &conflicts {
+                    warn!("  {}", file);
+                }
+                warn!("Use --link-overwrite to replace them, or run `brew link --overwrite {}`", formula.brew_name);
+                Ok(())
+            }
+            LinkMode::Link => Err(BrewverError::LinkConflict { name: formula.brew_name.clone(), files: conflicts }),
+            LinkMode::Overwrite => {
+                info!("Linking {}, replacing {} files of other formulas", formula.brew_name, conflicts.len());
+                self.run(&["link", "--overwrite", &formula.brew_name])
+            }
+        }
+    }
+
+    /// The files linking the formula would overwrite, as `brew link --overwrite --dry-run` lists them.
+    fn link_conflicts(&self, brew_name: &str) -> Vec<String> {
+        self.brew.output(&["link", "--overwrite", "--dry-run", brew_name])
+            .map(|output| output.lines().map(str::trim).filter(|line| line.starts_with('/')).map(String::from).collect())
+            .unwrap_or_default()
+    }
+
+    /// Records an install in the state and its history, warning when it can't be written.
+    fn record_managed(&self, name: &str, formula: &Formula, from: Option<&str>) {
+        if let Some(state) = &self.state {
+            if let Err(e) = state.record_managed(name, &formula.version, &formula.repo, &formula.commit, self.pin) {
+                warn!("Failed to record the install of {}: {}", name, e);
+            }
+        }
+        self.record_history(HistoryEntry::new("install", name).version(&formula.version).from(from).commit(&formula.commit));
+    }
+
+    fn record_history(&self, entry: HistoryEntry) {
+        if let Some(state) = &self.state {
+            if let Err(e) = state.record_history(&entry) {
+                warn!("Failed to record the {} of {} in the history: {}", entry.action, entry.formula, e);
+            }
+        }
+    }
+
+    fn install_extracted(&self, formula: &Formula, file: &FormulaFile) -> Result<()> {
+        let versioned_name = versioned_name(formula)?;
+        let tap = extract_tap_dir(self.brew.as_ref())?;
+        if !tap.exists() {
+            self.run(&["tap-new", "--no-git", EXTRACT_TAP])?;
+        }
+
+        let path = tap.join("Formula").join(format!("{}.rb", versioned_name));
+        std::fs::create_dir_all(tap.join("Formula"))?;
+        std::fs::write(&path, rename_class(&file.contents()?, &class_name(&versioned_name)))?;
+        info!("Extracted {} to {:?}", versioned_name, path);
+
+        let unlinked = !self.installed_versions(&formula.brew_name).is_empty();
+        if unlinked {
+            self.run(&["unlink", &formula.brew_name])?;
+            interrupt::set_unlinked(Some(&formula.brew_name));
+        }
+        let tap_name = format!("{}/{}", EXTRACT_TAP, versioned_name);
+        let installed = self.run(&self.install_command(&[&tap_name]));
+        interrupt::set_unlinked(None);
+        if let Err(e) = installed {
+            if unlinked {
+                warn!("Relinking {}", formula.brew_name);
+                if let Err(link_error) = self.run(&["link", &formula.brew_name]) {
+                    error!("Failed to relink {}: {}", formula.brew_name, link_error);
+                }
+            }
+            return Err(e);
+        }
+
+        if self.unlink_after {
+            self.run(&["unlink", &tap_name])?;
+        }
+        if self.pin {
+            self.run(&["pin", &tap_name])?;
+        }
+        self.record_managed(&versioned_name, formula, None);
+        Ok(())
+    }
+
+    /// Puts a keg of the formula version next to the linked one without touching links, and
+    /// returns its prefix. A keg of the version that is already in the Cellar is used as it is;
+    /// otherwise the formula file is installed as a keg-only `<name>@<version>` from the
+    /// [`EXTRACT_TAP`] tap, which [`Installer::verify`] checks brew lists afterwards.
+    pub fn install_keg(&self, formula: &Formula, file: &FormulaFile) -> Result<Keg> {
+        if self.has_keg(&formula.brew_name, &formula.version) {
+            let output = self.brew.output(&["--cellar", &formula.brew_name])?;
+            let prefix = PathBuf::from(output.trim()).join(&formula.version);
+            return Ok(Keg { name: formula.brew_name.clone(), prefix });
+        }
+
+        let versioned_name = versioned_name(formula)?;
+        let tap_name = format!("{}/{}", EXTRACT_TAP, versioned_name);
+        if self.installed_versions(&tap_name).is_empty() {
+            let tap = extract_tap_dir(self.brew.as_ref())?;
+            if !tap.exists() {
+                self.run(&["tap-new", "--no-git", EXTRACT_TAP])?;
+            }
+            let path = tap.join("Formula").join(format!("{}.rb", versioned_name));
+            std::fs::create_dir_all(tap.join("Formula"))?;
+            let contents = rename_class(&file.contents()?, &class_name(&versioned_name));
+            std::fs::write(&path, keg_only(&contents))?;
+            info!("Extracted {} to {:?}", versioned_name, path);
+            self.run(&self.install_command(&[&tap_name]))?;
+            if self.verify && self.installed_versions(&tap_name).is_empty() {
+                return Err(BrewverError::NotInstalled { name: tap_name, version: formula.version.clone(), installed: Vec::new() });
+            }
+        }
+        let output = self.brew.output(&["--prefix", &tap_name])?;
+        Ok(Keg { name: tap_name, prefix: PathBuf::from(output.trim()) })
+    }
+
+    /// The commands `install` would run for the given formula file.
+    pub fn plan(&self, formula: &Formula, formula_file: &str) -> Vec<Vec<String>> {
+        if formula.cask {
+            return vec![brew(&self.install_command(&["--cask", "--force", formula_file]))];
+        }
+        let mut commands = if self.audit { self.audit_plan(formula_file) } else { Vec::new() };
+        commands.extend(self.install_plan(formula, formula_file));
+        commands
+    }
+
+    fn install_plan(&self, formula: &Formula, formula_file: &str) -> Vec<Vec<String>> {
+        if self.extract {
+            return self.extract_plan(formula, formula_file);
+        }
+        if self.keep_current {
+            return self.keg_plan(formula, formula_file);
+        }
+
+        let mut commands = Vec::new();
+        let previous_versions = self.installed_versions(&formula.brew_name);
+        let service = self.restart_services && previous_versions.iter().any(|version| *version != formula.version)
+            && self.service_running(&formula.brew_name);
+        if service {
+            commands.push(brew(&["services", "stop", &formula.brew_name]));
+        }
+        if !previous_versions.is_empty() {
+            commands.push(brew(&["unlink", &formula.brew_name]));
+        }
+        commands.push(brew(&self.install_command(&[formula_file])));
+        for version in previous_versions.iter().filter(|version| !self.keep_kegs && **version != formula.version) {
+            commands.push(vec!["rm".to_string(), "-r".to_string(), format!("$(brew --cellar {})/{}", formula.brew_name, version)]);
+        }
+        if self.link_only {
+            commands.push(brew(&["link", "--overwrite", &formula.brew_name]));
+        } else if self.unlink_after {
+            commands.push(brew(&["unlink", &formula.brew_name]));
+        }
+        if self.pin {
+            commands.push(brew(&["pin", &formula.brew_name]));
+        }
+        if service {
+            commands.push(brew(&["services", "start", &formula.brew_name]));
+        }
+        commands
+    }
+
+    fn extract_plan(&self, formula: &Formula, formula_file: &str) -> Vec<Vec<String>> {
+        let versioned_name = versioned_name(formula).unwrap_or_else(|_| formula.brew_name.clone());
+        let tap_name = format!("{}/{}", EXTRACT_TAP, versioned_name);
+        let mut commands = Vec::new();
+        if !extract_tap_dir(self.brew.as_ref()).is_ok_and(|tap| tap.exists()) {
+            commands.push(brew(&["tap-new", "--no-git", EXTRACT_TAP]));
+        }
+        commands.push(vec![
+            "cp".to_string(),
+            formula_file.to_string(),
+            format!("$(brew --repository {})/Formula/{}.rb", EXTRACT_TAP, versioned_name),
+        ]);
+        if !self.installed_versions(&formula.brew_name).is_empty() {
+            commands.push(brew(&["unlink", &formula.brew_name]));
+        }
+        commands.push(brew(&self.install_command(&[&tap_name])));
+        if self.unlink_after {
+            commands.push(brew(&["unlink", &tap_name]));
+        }
+        if self.pin {
+            commands.push(brew(&["pin", &tap_name]));
+        }
+        commands
+    }
+
+    fn keg_plan(&self, formula: &Formula, formula_file: &str) -> Vec<Vec<String>> {
+        if self.has_keg(&formula.brew_name, &formula.version) {
+            return Vec::new();
+        }
+        let versioned_name = versioned_name(formula).unwrap_or_else(|_| formula.brew_name.clone());
+        let tap_name = format!("{}/{}", EXTRACT_TAP, versioned_name);
+        if !self.installed_versions(&tap_name).is_empty() {
+            return Vec::new();
+        }
+        let mut commands = Vec::new();
+        if !extract_tap_dir(self.brew.as_ref()).is_ok_and(|tap| tap.exists()) {
+            commands.push(brew(&["tap-new", "--no-git", EXTRACT_TAP]));
+        }
+        commands.push(vec![
+            "cp".to_string(),
+            formula_file.to_string(),
+            format!("$(brew --repository {})/Formula/{}.rb", EXTRACT_TAP, versioned_name),
+        ]);
+        commands.push(brew(&self.install_command(&[&tap_name])));
+        commands
+    }
+
+    /// The commands `link` would run.
+    pub fn link_plan(&self, brew_name: &str) -> Vec<Vec<String>> {
+        let mut commands = vec![brew(&["link", "--overwrite", brew_name])];
+        if self.pin {
+            commands.push(brew(&["pin", brew_name]));
+        }
+        commands
+    }
+
+    /// Links an installed keg, overwriting conflicting files, and pins it if pinning is on.
+    pub fn link(&self, brew_name: &str) -> Result<()> {
+        self.run(&["link", "--overwrite", brew_name])?;
+        if self.pin {
+            self.run(&["pin", brew_name])?;
+        }
+        Ok(())
+    }
+
+    /// Links another installed keg of the formula in place of the linked one, like the former
+    /// `brew switch`: the opt link is pointed at the keg, which is the keg `brew link` picks when
+    /// several are installed.
+    pub fn switch(&self, brew_name: &str, version: &str) -> Result<()> {
+        let output = self.brew.output(&["--cellar", brew_name])?;
+        let keg = PathBuf::from(output.trim()).join(version);
+        if !keg.is_dir() {
+            return Err(BrewverError::KegNotInstalled { name: brew_name.to_string(), version: version.to_string() });
+        }
+        let keg = keg.to_str().ok_or_else(|| BrewverError::InvalidPath(keg.clone()))?;
+        let output = self.brew.output(&["--prefix", brew_name])?;
+        let opt = output.trim().to_string();
+
+        let previous = self.linked_version(brew_name);
+        if previous.is_some() {
+            self.run(&["unlink", brew_name])?;
+            interrupt::set_unlinked(Some(brew_name));
+        }
+        run_command("ln", &["-sfn", keg, &opt])?;
+        self.run(&["link", brew_name])?;
+        interrupt::set_unlinked(None);
+        if let (Some(state), Some(previous)) = (&self.state, previous.as_ref().filter(|previous| *previous != version)) {
+            if let Err(e) = state.record_previous_version(brew_name, previous) {
+                warn!("Failed to record the previous version of {}: {}", brew_name, e);
+            }
+        }
+        self.record_history(HistoryEntry::new("switch", brew_name).version(version).from(previous.as_deref()));
+        Ok(())
+    }
+
+    /// The commands `switch` would run.
+    pub fn switch_plan(&self, brew_name: &str, version: &str) -> Vec<Vec<String>> {
+        let mut commands = Vec::new();
+        if self.linked_version(brew_name).is_some() {
+            commands.push(brew(&["unlink", brew_name]));
+        }
+        commands.push(vec![
+            "ln".to_string(),
+            "-sfn".to_string(),
+            format!("$(brew --cellar {})/{}", brew_name, version),
+            format!("$(brew --prefix {})", brew_name),
+        ]);
+        commands.push(brew(&["link", brew_name]));
+        commands
+    }
+
+    /// The installed formulas brew has a newer version of.
+    pub fn outdated_formulas(&self) -> Result<Vec<String>> {
+        let output = self.brew.output(&["outdated", "--formula", "--json=v2"])?;
+        let json: serde_json::Value = serde_json::from_str(&output)?;
+        Ok(json.get("formulae")
+            .and_then(|f| f.as_array())
+            .map(|formulae| formulae.iter().filter_map(|f| f.get("name").and_then(|n| n.as_str()).map(String::from)).collect())
+            .unwrap_or_default())
+    }
+
+    /// Upgrades the given formulas to their latest version.
+    pub fn upgrade(&self, brew_names: &[&str]) -> Result<()> {
+        let args: Vec<&str> = ["upgrade", "--formula"].into_iter().chain(brew_names.iter().copied()).collect();
+        self.run(&args)?;
+        for brew_name in brew_names {
+            self.record_history(HistoryEntry::new("upgrade", brew_name));
+        }
+        Ok(())
+    }
+
+    /// The command `upgrade` would run.
+    pub fn upgrade_plan(&self, brew_names: &[&str]) -> Vec<Vec<String>> {
+        let args: Vec<&str> = ["upgrade", "--formula"].into_iter().chain(brew_names.iter().copied()).collect();
+        vec![brew(&args)]
+    }
+
+    /// Releases a pin so `brew upgrade` updates the formula again.
+    pub fn unpin(&self, brew_name: &str) -> Result<()> {
+        self.run(&["unpin", brew_name])?;
+        Ok(())
+    }
+
+    /// Uninstalls every installed version of a formula after releasing its pin, and forgets that
+    /// brewver installed it.
+    pub fn uninstall(&self, brew_name: &str) -> Result<()> {
+        if let Some(confirm) = &self.confirm {
+            let versions = self.installed_versions(brew_name);
+            if versions.len() > 1 {
+                confirm(&format!("Uninstalling {} will remove versions {}. Continue?", brew_name, versions.join(", ")))?;
+            }
+        }
+        for command in self.uninstall_plan(brew_name) {
+            let args: Vec<&str> = command.iter().skip(1).map(String::as_str).collect();
+            self.run(&args)?;
+        }
+        if let Some(state) = &self.state {
+            if let Err(e) = state.forget_managed(brew_name) {
+                warn!("Failed to forget the install of {}: {}", brew_name, e);
+            }
+        }
+        self.record_history(HistoryEntry::new("uninstall", brew_name));
+        Ok(())
+    }
+
+    /// The commands `uninstall` would run.
+    pub fn uninstall_plan(&self, brew_name: &str) -> Vec<Vec<String>> {
+        let mut commands = Vec::new();
+        if self.is_pinned(brew_name) {
+            commands.push(brew(&["unpin", brew_name]));
+        }
+        commands.push(brew(&["uninstall", "--formula", "--force", brew_name]));
+        commands
+    }
+
+    /// Whether brew lists the formula as pinned.
+    fn is_pinned(&self, brew_name: &str) -> bool {
+        self.brew.output(&["list", "--pinned", "--formula"])
+            .is_ok_and(|output| output.lines().any(|line| line.trim() == brew_name))
+    }
+
+    /// Downloads the bottle of a formula file for this machine into brew's cache, returning its path.
+    pub fn fetch_bottle(&self, file: &FormulaFile) -> Result<PathBuf> {
+        let formula_file = file.path().to_str().ok_or_else(|| BrewverError::InvalidPath(file.path().to_path_buf()))?;
+        self.run(&["fetch", "--formula", formula_file])?;
+        self.bottle_cache_path(formula_file)
+    }
+
+    /// Copies a bottle into brew's cache, where installing the formula file finds it instead of
+    /// downloading it.
+    pub fn cache_bottle(&self, file: &FormulaFile, bottle: &Path) -> Result<()> {
+        let formula_file = file.path().to_str().ok_or_else(|| BrewverError::InvalidPath(file.path().to_path_buf()))?;
+        let cached = self.bottle_cache_path(formula_file)?;
+        if let Some(dir) = cached.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        debug!("Copying {:?} to {:?}", bottle, cached);
+        std::fs::copy(bottle, cached)?;
+        Ok(())
+    }
+
+    fn bottle_cache_path(&self, formula_file: &str) -> Result<PathBuf> {
+        let output = self.brew.output(&["--cache", "--formula", formula_file])?;
+        Ok(PathBuf::from(output.trim()))
+    }
+
+    /// Where brew's cache keeps the source archive of a formula file, named as brew looks for it
+    /// when building the formula from source.
+    pub fn source_cache_path(&self, file: &FormulaFile) -> Result<PathBuf> {
+        let formula_file = file.path().to_str().ok_or_else(|| BrewverError::InvalidPath(file.path().to_path_buf()))?;
+        let output = self.brew.output(&["--cache", "--build-from-source", "--formula", formula_file])?;
+        Ok(PathBuf::from(output.trim()))
+    }
+
+    /// Installs a formula by name, the way brew itself would.
+    pub fn install_by_name(&self, brew_name: &str) -> Result<()> {
+        self.run(&self.install_command(&[brew_name]))?;
+        if self.pin {
+            self.run(&["pin", brew_name])?;
+        }
+        Ok(())
+    }
+
+    /// `brew install` with the given arguments followed by the extra install arguments.
+    fn install_command<'a>(&'a self, args: &[&'a str]) -> Vec<&'a str> {
+        let mut command = vec!["install"];
+        command.extend_from_slice(args);
+        command.extend(self.install_args.iter().map(String::as_str));
+        command
+    }
+
+    /// Checks whether a keg of the version is already present in the Cellar.
+    pub fn has_keg(&self, brew_name: &str, version: &str) -> bool {
+        self.installed_versions(brew_name).iter().any(|installed| installed == version)
+    }
+
+    /// Runs a brew command that changes the installation.
+    fn run(&self, args: &[&str]) -> Result<()> {
+        self.brew.run(args, self.quiet)
+    }
+
+    /// The version line brew prints, e.g. `Homebrew 4.2.0`.
+    pub fn brew_version(&self) -> Result<String> {
+        let output = self.brew.output(&["--version"])?;
+        Ok(output.lines().next().unwrap_or_default().to_string())
+    }
+
+    /// Checks that brew is recent enough for the commands brewver runs, before any of them
+    /// fails halfway through an install. A version that can't be read, as with some development
+    /// builds, is let through.
+    pub fn check_brew_version(&self) -> Result<Option<HomebrewVersion>> {
+        let line = self.brew_version()?;
+        let Some(version) = HomebrewVersion::parse(&line) else {
+            warn!("Couldn't read the version of brew from {:?}, assuming it is recent enough", line);
+            return Ok(None);
+        };
+        debug!("brew {}", version);
+        if version < MIN_BREW_VERSION {
+            return Err(BrewverError::BrewTooOld { found: version.to_string(), required: MIN_BREW_VERSION.to_string() });
+        }
+        Ok(Some(version))
+    }
+
+    /// The opt prefix of the formula, `$(brew --prefix)/opt/<name>`, which points to its linked or
+    /// newest keg.
+    pub fn opt_prefix(&self, brew_name: &str) -> Option<PathBuf> {
+        let output = self.brew.output(&["--prefix", brew_name]).ok()?;
+        Some(PathBuf::from(output.trim())).filter(|prefix| !prefix.as_os_str().is_empty())
+    }
+
+    /// The version of the formula brew has linked, if any.
+    pub fn linked_version(&self, brew_name: &str) -> Option<String> {
+        let output = self.brew.output(&["info", "--json=v2", brew_name]).ok()?;
+        let json: serde_json::Value = serde_json::from_str(&output).ok()?;
+        json.pointer("/formulae/0/linked_keg")?.as_str().map(String::from)
+    }
+
+    /// Lists the installed formulas, each with its installed versions.
+    pub fn installed_formulas(&self) -> Result<Vec<(String, Vec<String>)>> {
+        let output = self.brew.output(&["list", "--versions", "--formula"])?;
+        Ok(output.lines().filter_map(|line| {
+            let mut words = line.split_whitespace().map(String::from);
+            Some((words.next()?, words.collect()))
+        }).collect())
+    }
+
+    /// Lists the installed versions of the formula; `brew list` fails when there are none.
+    pub fn installed_versions(&self, brew_name: &str) -> Vec<String> {
+        self.brew.output(&["list", "--versions", brew_name])
+            .map(|output| {
+                output
+                    .split_whitespace()
+                    .skip(1)
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The installed versions of the formula other than the one to keep and the linked one.
+    pub fn superseded_versions(&self, brew_name: &str, keep: &str) -> Vec<String> {
+        let linked = self.linked_version(brew_name);
+        self.installed_versions(brew_name).into_iter()
+            .filter(|version| version != keep && linked.as_ref() != Some(version))
+            .collect()
+    }
+
+    /// Removes the kegs of the installed versions of the formula other than the one to keep, and
+    /// lets brew clean up its old downloads.
+    pub fn cleanup(&self, brew_name: &str, keep: &str) -> Result<Vec<String>> {
+        let versions = self.superseded_versions(brew_name, keep);
+        if versions.is_empty() {
+            return Ok(versions);
+        }
+        remove_kegs(self.brew.as_ref(), brew_name, keep, &versions)?;
+        self.run(&["cleanup", brew_name])?;
+        for version in &versions {
+            self.record_history(HistoryEntry::new("cleanup", brew_name).version(version));
+        }
+        Ok(versions)
+    }
+
+    /// The installed formulas that depend on the formula. When brew can't tell, none are assumed.
+    pub fn dependents(&self, brew_name: &str) -> Vec<String> {
+        match self.brew.output(&["uses", "--installed", brew_name]) {
+            Ok(output) => output.split_whitespace().map(String::from).collect(),
+            Err(e) => {
+                warn!("Failed to list the formulas that depend on {}: {}", brew_name, e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Uses brew's own metadata to report the available versions, installing the formula
+    /// if its current stable version is the requested one.
+    pub fn install_stable(&self, brew_name: &str, version: &str) -> Result<()> {
+        let output = self.brew.output(&["info", "--json=v2", brew_name])?;
+        let json: serde_json::Value = serde_json::from_str(&output)?;
+        let formula = json.get("formulae")
+            .and_then(|f| f.as_array())
+            .and_then(|f| f.first())
+            .ok_or_else(|| BrewverError::NotKnownToBrew { name: brew_name.to_string() })?;
+
+        let stable = formula.pointer("/versions/stable").and_then(|v| v.as_str()).unwrap_or("none");
+        let installed: Vec<&str> = formula.get("installed")
+            .and_then(|i| i.as_array())
+            .map(|kegs| kegs.iter().filter_map(|keg| keg.get("version").and_then(|v| v.as_str())).collect())
+            .unwrap_or_default();
+        info!("brew knows {}: stable version {}, installed versions: {}", brew_name, stable, installed.join(", "));
+
+        if stable != version {
+            return Err(BrewverError::NotStableVersion { name: brew_name.to_string(), version: version.to_string(), stable: stable.to_string() });
+        }
+        self.run(&self.install_command(&[brew_name]))?;
+        Ok(())
+    }
+}
+
+/// Whether the keg version brew lists is the version, possibly with a revision like `1.21.3_1`.
+fn same_version(keg_version: &str, version: &str) -> bool {
+    keg_version == version || keg_version.strip_prefix(version).is_some_and(|rest| rest.starts_with('_'))
+}
+
+/// What a binary prints for `--version`, unless it fails or takes more than a few seconds.
+fn version_output(binary: &Path) -> Option<String> {
+    let mut child = std::process::Command::new(binary)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()?;
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    while child.try_wait().ok()?.is_none() {
+        if std::time::Instant::now() > deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return None;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    let output = child.wait_with_output().ok()?;
+    // Some tools print their version to stderr.
+    Some(format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr)))
+}
+
+/// How many times the size of a bottle its install takes in the Cellar, as bottles are compressed.
+const BOTTLE_EXPANSION: u64 = 3;
+
+/// How many times the size of a source archive a build takes in the temporary directory, for the
+/// unpacked sources and what is built from them.
+const BUILD_EXPANSION: u64 = 10;
+
+/// Where brew builds formulas, `HOMEBREW_TEMP` or else the system's temporary directory.
+fn brew_temp_dir() -> PathBuf {
+    std::env::var_os("HOMEBREW_TEMP").filter(|dir| !dir.is_empty()).map(PathBuf::from).unwrap_or_else(std::env::temp_dir)
+}
+
+/// The mount point of the file system a path is on, and the bytes free on it, as `df` reports
+/// them.
+fn free_space(path: &Path) -> Option<(String, u64)> {
+    let path = path.ancestors().find(|dir| dir.exists())?.to_str()?;
+    let output = run_command("df", &["-Pk", path]).ok()?;
+    let output = String::from_utf8_lossy(&output.stdout);
+    let fields: Vec<&str> = output.lines().nth(1)?.split_whitespace().collect();
+    let available: u64 = fields.get(3)?.parse().ok()?;
+    Some((fields.last()?.to_string(), available * 1024))
+}
+
+/// A size in bytes as megabytes, rounded up.
+fn megabytes(bytes: u64) -> u64 {
+    bytes.div_ceil(1024 * 1024)
+}
+
+/// Warns that a service going back to an older version may not read the data the newer one
+/// wrote, as with the data directory of postgresql across major versions.
+fn warn_data_format(formula: &Formula, previous_versions: &[String]) {
+    let Some(newer) = previous_versions.iter().filter(|version| version_key(version) > version_key(&formula.version)).max_by_key(|version| version_key(version)) else {
+        return;
+    };
+    warn!("{} {} may not read the data {} {} wrote, since data formats can change between versions", formula.brew_name, formula.version, formula.brew_name, newer);
+    warn!("Back up its data before the service starts again, or dump it with {} and restore it into {}", newer, formula.version);
+}
+
+/// Removes the kegs of the versions other than the one to keep, such as the previously installed
+/// ones once the requested one is in place.
+fn remove_kegs(brew: &dyn BrewRunner, brew_name: &str, keep: &str, versions: &[String]) -> Result<()> {
+    let versions: Vec<&String> = versions.iter().filter(|version| *version != keep).collect();
+    if versions.is_empty() {
+        return Ok(());
+    }
+
+    let output = brew.output(&["--cellar", brew_name])?;
+    let cellar = PathBuf::from(output.trim());
+    for version in versions {
+        info!("Removing {} {}", brew_name, version);
+        std::fs::remove_dir_all(cellar.join(version))?;
+    }
+    Ok(())
+}
+
+fn extract_tap_dir(brew: &dyn BrewRunner) -> Result<PathBuf> {
+    let output = brew.output(&["--repository", EXTRACT_TAP])?;
+    Ok(PathBuf::from(output.trim()))
+}
+
+/// The `<name>@<version>` name of the extracted formula.
+fn versioned_name(formula: &Formula) -> Result<String> {
+    if formula.version.is_empty() {
+        return Err(BrewverError::NoVersion { name: formula.brew_name.clone() });
+    }
+    Ok(format!("{}@{}", formula.brew_name, formula.version))
+}
+
+/// The Ruby class brew expects for a formula name, e.g. `WgetAT1213` for `wget@1.21.3`.
+fn class_name(name: &str) -> String {
+    let mut class = String::new();
+    let mut upper = true;
+    let mut chars = name.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '-' | '_' | '.' => upper = true,
+            '+' => class.push('x'),
+            '@' if chars.peek().is_some_and(|next| next.is_ascii_digit()) => class.push_str("AT"),
+            c if upper => {
+                class.push(c.to_ascii_uppercase());
+                upper = false;
+            }
+            c => class.push(c),
+        }
+    }
+    class
+}
+
+/// Renames the formula class in a formula file.
+fn rename_class(contents: &str, class: &str) -> String {
+    contents.lines()
+        .map(|line| match line.strip_prefix("class ").and_then(|rest| rest.split_once(" < Formula")) {
+            Some((_, rest)) => format!("class {} < Formula{}", class, rest),
+            None => line.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n") + "\n"
+}
+
+/// Marks a formula keg-only, like the versioned formulas of homebrew-core, so brew doesn't link
+/// it over the unversioned formula.
+fn keg_only(contents: &str) -> String {
+    if contents.lines().any(|line| line.trim_start().starts_with("keg_only")) {
+        return contents.to_string();
+    }
+    contents.lines()
+        .flat_map(|line| {
+            if line.starts_with("class ") {
+                vec![line.to_string(), "  keg_only :versioned_formula".to_string()]
+            } else {
+                vec![line.to_string()]
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n") + "\n"
+}
+
+fn brew(args: &[&str]) -> Vec<String> {
+    std::iter::once("brew").chain(args.iter().copied()).map(String::from).collect()
+}
+
+/// Runs a command with the environment variables, logging its output as it is printed. Stderr is
+/// kept for the error.
+fn stream_command(command: &str, args: &[&str], env: &[(&str, &str)], timeout: Option<Duration>) -> Result<()> {
+    let command_line = format!("{} {}", command, args.join(" "));
+    let not_run = |source| BrewverError::CommandNotRun { command: command_line.clone(), source };
+    let mut child = std::process::Command::new(command);
+    child.args(args)
+        .envs(env.iter().copied())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let mut child = spawn(&mut child, timeout).map_err(not_run)?;
+    let watchdog = timeout.map(|timeout| Watchdog::start(child.id(), timeout));
+
+    let stderr = child.stderr.take().map(|stderr| std::thread::spawn(move || {
+        BufReader::new(stderr).lines()
+            .map_while(std::io::Result::ok)
+            .inspect(|line| {
+                info!(target: "brew", "{}", line);
+                progress::report(|| ProgressEvent::InstallOutputLine { line: line.clone() });
+            })
+            .collect::<Vec<String>>()
+    }));
+    if let Some(stdout) = child.stdout.take() {
+        for line in BufReader::new(stdout).lines().map_while(std::io::Result::ok) {
+            info!(target: "brew", "{}", line);
+            progress::report(|| ProgressEvent::InstallOutputLine { line });
+        }
+    }
+    let stderr = stderr.and_then(|thread| thread.join().ok()).unwrap_or_default();
+    let status = child.wait().map_err(not_run);
+    interrupt::set_child(None, false);
+    let status = status?;
+
+    if let (Some(watchdog), Some(timeout)) = (watchdog, timeout) {
+        if watchdog.fired() {
+            return Err(BrewverError::BrewTimedOut { command: command_line, timeout });
+        }
+    }
+    if !status.success() {
+        return Err(BrewverError::BrewFailed { command: command_line, status: status.to_string(), code: status.code(), stderr: stderr.join("\n").trim().to_string() });
+    }
+    Ok(())
+}
+
+pub(crate) fn run_command(command: &str, args: &[&str]) -> Result<std::process::Output> {
+    run_command_with(command, args, &[])
+}
+
+/// Runs a command with the environment variables, returning its output.
+fn run_command_with(command: &str, args: &[&str], env: &[(&str, &str)]) -> Result<std::process::Output> {
+    run_command_within(command, args, env, None)
+}
+
+/// Runs a command like [`run_command_with`], stopping it once it runs longer than the timeout.
+fn run_command_within(command: &str, args: &[&str], env: &[(&str, &str)], timeout: Option<Duration>) -> Result<std::process::Output> {
+    let command_line = format!("{} {}", command, args.join(" "));
+    let not_run = |source| BrewverError::CommandNotRun { command: command_line.clone(), source };
+    let mut child = std::process::Command::new(command);
+    child.args(args)
+        .envs(env.iter().copied())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let child = spawn(&mut child, timeout).map_err(not_run)?;
+    let watchdog = timeout.map(|timeout| Watchdog::start(child.id(), timeout));
+    let output = child.wait_with_output().map_err(not_run);
+    interrupt::set_child(None, false);
+    let output = output?;
+    debug!("Command output: {:?}", output);
+
+    if let (Some(watchdog), Some(timeout)) = (watchdog, timeout) {
+        if watchdog.fired() {
+            return Err(BrewverError::BrewTimedOut { command: command_line, timeout });
+        }
+    }
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(BrewverError::BrewFailed { command: command_line, status: output.status.to_string(), code: output.status.code(), stderr: stderr.trim().to_string() });
+    }
+    Ok(output)
+}
+
+/// Spawns the command and marks it as the running one. A command with a timeout gets a process
+/// group of its own, so that it can be stopped together with the processes it starts, and no
+/// terminal input, which it couldn't read from outside the foreground group.
+fn spawn(command: &mut std::process::Command, timeout: Option<Duration>) -> std::io::Result<std::process::Child> {
+    if timeout.is_some() {
+        command.stdin(Stdio::null());
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(command, 0);
+    }
+    let child = command.spawn()?;
+    interrupt::set_child(Some(child.id()), timeout.is_some());
+    Ok(child)
+}
+
+/// Stops a process group once it runs longer than the timeout: with SIGTERM, and with SIGKILL when
+/// it is still running a few seconds later.
+struct Watchdog {
+    finished: mpsc::Sender<()>,
+    fired: Arc<AtomicBool>,
+}
+
+impl Watchdog {
+    fn start(pid: u32, timeout: Duration) -> Self {
+        let (finished, wait) = mpsc::channel();
+        let fired = Arc::new(AtomicBool::new(false));
+        let watchdog_fired = fired.clone();
+        std::thread::spawn(move || {
+            if wait.recv_timeout(timeout) != Err(mpsc::RecvTimeoutError::Timeout) {
+                return;
+            }
+            warn!("Stopping brew, it has been running for longer than {}s", timeout.as_secs());
+            watchdog_fired.store(true, Ordering::SeqCst);
+            interrupt::kill_group(pid, "TERM");
+            if wait.recv_timeout(Duration::from_secs(10)) == Err(mpsc::RecvTimeoutError::Timeout) {
+                interrupt::kill_group(pid, "KILL");
+            }
+        });
+        Watchdog { finished, fired }
+    }
+
+    /// Whether the timeout passed before the process finished.
+    fn fired(self) -> bool {
+        drop(self.finished);
+        self.fired.load(Ordering::SeqCst)
+    }
+}
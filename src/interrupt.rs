@@ -0,0 +1,107 @@
+//! Keeps track of what an interrupted run leaves behind: the command it is running, its
+//! temporary directories and a formula that was unlinked for an install that didn't finish.
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use log::{error, info, warn};
+use tempfile::{Builder, TempDir};
+
+use crate::error::Result;
+use crate::installer::brew_command;
+
+static RUNNING: Mutex<Running> = Mutex::new(Running { child: None, child_group: false, temp_dirs: Vec::new(), unlinked: None, keep_temp: None });
+
+struct Running {
+    child: Option<u32>,
+    /// Whether the child leads a process group of its own, which is stopped as a whole.
+    child_group: bool,
+    temp_dirs: Vec<PathBuf>,
+    unlinked: Option<String>,
+    /// Where temporary directories are kept rather than removed, if anywhere, `None` within it
+    /// for the system's temporary directory.
+    keep_temp: Option<Option<PathBuf>>,
+}
+
+fn running() -> std::sync::MutexGuard<'static, Running> {
+    RUNNING.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+pub(crate) fn set_child(pid: Option<u32>, group: bool) {
+    let mut running = running();
+    running.child = pid;
+    running.child_group = group;
+}
+
+/// Sends the signal to every process of the group the process leads.
+pub(crate) fn kill_group(pid: u32, signal: &str) {
+    if let Err(e) = std::process::Command::new("kill").args([&format!("-{}", signal), "--", &format!("-{}", pid)]).status() {
+        warn!("Failed to stop process group {}: {}", pid, e);
+    }
+}
+
+pub(crate) fn add_temp_dir(path: &Path) {
+    running().temp_dirs.push(path.to_path_buf());
+}
+
+/// Keeps the formula files, bottles and unpacked bundles of the run instead of removing them, in
+/// the work directory if given, else in the system's temporary directory.
+pub fn keep_temp_dirs(workdir: Option<&Path>) {
+    running().keep_temp = Some(workdir.map(Path::to_path_buf));
+}
+
+/// A new `brewver-*` directory for the files of the run, removed when dropped or on an interrupt
+/// unless they are kept.
+pub(crate) fn temp_dir() -> Result<TempDir> {
+    let keep_temp = running().keep_temp.clone();
+    let mut builder = Builder::new();
+    builder.prefix("brewver-").keep(keep_temp.is_some());
+    let dir = match &keep_temp {
+        Some(Some(workdir)) => {
+            std::fs::create_dir_all(workdir)?;
+            builder.tempdir_in(workdir)?
+        }
+        _ => builder.tempdir()?,
+    };
+    if keep_temp.is_some() {
+        info!("Keeping temporary files in {}", dir.path().display());
+    } else {
+        add_temp_dir(dir.path());
+    }
+    Ok(dir)
+}
+
+pub(crate) fn remove_temp_dir(path: &Path) {
+    running().temp_dirs.retain(|dir| dir != path);
+}
+
+/// Marks the formula as unlinked until its new version is installed, or relinked.
+pub(crate) fn set_unlinked(brew_name: Option<&str>) {
+    running().unlinked = brew_name.map(String::from);
+}
+
+/// Undoes what an interrupted run left behind: stops the command it was running, removes its
+/// temporary directories and relinks the previous version of a formula whose install didn't
+/// finish. Meant to be called from a signal handler right before exiting.
+pub fn cleanup_interrupted() {
+    let mut running = running();
+    if let Some(pid) = running.child.take() {
+        info!("Stopping the running command");
+        if running.child_group {
+            kill_group(pid, "TERM");
+        } else if let Err(e) = std::process::Command::new("kill").arg(pid.to_string()).status() {
+            warn!("Failed to stop process {}: {}", pid, e);
+        }
+    }
+    for dir in running.temp_dirs.drain(..) {
+        if let Err(e) = std::fs::remove_dir_all(&dir) {
+            warn!("Failed to remove {:?}: {}", dir, e);
+        }
+    }
+    if let Some(brew_name) = running.unlinked.take() {
+        info!("Relinking the previous version of {}", brew_name);
+        let relinked = std::process::Command::new(brew_command()).args(["link", &brew_name]).status();
+        if !relinked.is_ok_and(|status| status.success()) {
+            error!("Failed to relink {}, run `brew link {}` to restore the previous version", brew_name, brew_name);
+        }
+    }
+}
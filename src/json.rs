@@ -0,0 +1,12 @@
+//! Writes the JSON that `--output json` prints, either on one line or pretty-printed.
+
+use serde::Serialize;
+
+use crate::error::Result;
+
+/// Serializes a value as JSON, on a single line when `compact` or else indented over several
+/// lines. Both forms parse to the same value.
+pub fn to_json(value: &impl Serialize, compact: bool) -> Result<String> {
+    let json = if compact { serde_json::to_string(value)? } else { serde_json::to_string_pretty(value)? };
+    Ok(json)
+}
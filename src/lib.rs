@@ -0,0 +1,55 @@
+//! Installs specific versions of Homebrew formulas by finding the homebrew-core commit that
+//! bottled them and installing the formula file from that commit.
+//!
+//! The steps the `brewver` CLI runs are available separately: a [`FormulaResolver`] finds the
+//! formula file of a version, [`Formula::download`] fetches it and an [`Installer`] hands it to
+//! brew.
+//!
+//! ```no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let client = brewver::Client::new();
+//! let formula = brewver::FormulaResolver::new(&client).resolve("wget", "1.21.3")?;
+//! let file = formula.download(&client)?;
+//! brewver::Installer::new().install(&formula, &file)?;
+//! # Ok(())
+//! # }
+//! ```
+
+mod audit;
+mod backends;
+mod bottle;
+mod bundle;
+mod cache;
+mod client;
+mod error;
+mod formula;
+mod git;
+mod github;
+mod hook;
+mod installer;
+mod interrupt;
+mod json;
+#[cfg(feature = "mock")]
+pub mod mock;
+mod progress;
+mod resolver;
+mod state;
+mod stats;
+
+pub use audit::{advisories, Advisory, DEFAULT_OSV_ECOSYSTEM, OSV_QUERY_URL};
+pub use backends::{builtin as builtin_resolver, BrewApiResolver, GitResolver, GraphqlResolver, RestResolver, VersionResolver, BUILTIN_RESOLVERS};
+pub use bottle::{bottle_coverage, is_apple_silicon, set_bottle_domain, BottleDownload, BottleFile, BOTTLE_PLATFORMS, DEFAULT_BOTTLE_ROOT_URL};
+pub use bundle::Bundle;
+pub use cache::{Cache, CachePolicy, CacheSummary};
+pub use client::{env_github_token, gh_cli_token, record_requests, recorded_requests, set_max_concurrent_requests, Client, ClientBuilder, DownloadProgress, GithubClient, RequestRecord, Response, TokenKind, TokenStatus, DEFAULT_MAX_CONCURRENT_REQUESTS};
+pub use error::{BrewverError, Result};
+pub use formula::{Bottle, Dependency, Formula, FormulaFile, FormulaInfo, Retirement};
+pub use github::tap_repo;
+pub use hook::hook_args;
+pub use interrupt::{cleanup_interrupted, keep_temp_dirs};
+pub use json::to_json;
+pub use installer::{brew_command, brew_installed, set_brew_prefix, set_install_timeout, BrewRunner, Confirm, HomebrewVersion, Installer, Keg, LinkMode, RosettaBrew, SystemBrew, EXTRACT_TAP, MIN_BREW_VERSION, ROSETTA_BREW};
+pub use progress::{set_progress, Progress, ProgressEvent};
+pub use resolver::{brew_name, is_version_pattern, Backend, FormulaResolver, FormulaVersion, MatchStrategy, RequestEstimate, Source, LATEST};
+pub use state::{utc_timestamp, HistoryEntry, ManagedFormula, State, StateLock};
+pub use stats::{run_stats, RunStats};
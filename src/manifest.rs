@@ -0,0 +1,156 @@
+//! Manifests listing the formula versions of an environment, one `formula = "version"` pair
+//! per line, for `brewver sync`:
+//!
+//! ```toml
+//! node = "18.17.1"
+//! jq = "1.6"
+//! "hashicorp/tap/terraform" = "1.5.0"
+//! ```
+//!
+//! The commit and checksum of the formula file each version resolved to are kept in a
+//! `brewver.lock` next to the manifest, so every machine installs the same files.
+//!
+//! Projects can also pin their toolchain in a `.brew-versions` file for `brewver use`, with one
+//! `formula version` pair per line like asdf's `.tool-versions`.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use brewver::{BrewverError, Formula};
+
+/// The manifest `sync` reads when no other one is given.
+pub(crate) const DEFAULT_MANIFEST: &str = ".brewver.toml";
+
+const LOCKFILE: &str = "brewver.lock";
+
+const VERSION_FILE: &str = ".brew-versions";
+
+/// A formula version as resolved for the lockfile.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(crate) struct LockedFormula {
+    #[serde(flatten)]
+    pub(crate) formula: Formula,
+    /// The SHA-256 checksum of the formula file.
+    pub(crate) sha256: String,
+}
+
+/// Reads the formula versions of a manifest, by the name they are listed under.
+pub(crate) fn read_manifest(path: &Path) -> Result<BTreeMap<String, String>, BrewverError> {
+    let invalid = |reason: String| BrewverError::InvalidManifest { path: path.to_path_buf(), reason };
+    let contents = std::fs::read_to_string(path).map_err(|e| invalid(e.to_string()))?;
+    toml::from_str(&contents).map_err(|e| invalid(e.to_string()))
+}
+
+/// Finds the `.brew-versions` file in a directory or the closest of its parents.
+pub(crate) fn find_version_file(dir: &Path) -> Result<PathBuf, BrewverError> {
+    dir.ancestors()
+        .map(|dir| dir.join(VERSION_FILE))
+        .find(|path| path.is_file())
+        .ok_or_else(|| BrewverError::NoVersionFile(dir.to_path_buf()))
+}
+
+/// Reads the `name@version` specs of a `.brew-versions` file, skipping blank lines and comments.
+pub(crate) fn read_version_file(path: &Path) -> Result<Vec<String>, BrewverError> {
+    let invalid = |reason: String| BrewverError::InvalidManifest { path: path.to_path_buf(), reason };
+    let contents = std::fs::read_to_string(path).map_err(|e| invalid(e.to_string()))?;
+    contents.lines()
+        .enumerate()
+        .map(|(number, line)| (number + 1, line.split('#').next().unwrap_or_default().trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(number, line)| match line.split_whitespace().collect::<Vec<_>>()[..] {
+            [name, version] => Ok(format!("{}@{}", name, version)),
+            _ => Err(invalid(format!("line {} is not a `formula version` pair", number))),
+        })
+        .collect()
+}
+
+/// The lockfile kept next to a manifest.
+pub(crate) fn lockfile_path(manifest: &Path) -> PathBuf {
+    manifest.with_file_name(LOCKFILE)
+}
+
+/// Reads a lockfile, which is empty until the first `sync` writes it.
+pub(crate) fn read_lockfile(path: &Path) -> Result<BTreeMap<String, LockedFormula>, BrewverError> {
+    let invalid = |reason: String| BrewverError::InvalidManifest { path: path.to_path_buf(), reason };
+    match std::fs::read_to_string(path) {
+        Ok(contents) => toml::from_str(&contents).map_err(|e| invalid(e.to_string())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(invalid(e.to_string())),
+    }
+}
+
+pub(crate) fn write_lockfile(path: &Path, formulas: &BTreeMap<String, LockedFormula>) -> Result<(), BrewverError> {
+    let contents = toml::to_string(formulas)
+        .map_err(|e| BrewverError::InvalidManifest { path: path.to_path_buf(), reason: e.to_string() })?;
+    std::fs::write(path, format!("# Written by brewver, do not edit.\n\n{}", contents))?;
+    Ok(())
+}
+
+/// Writes a manifest of the formula versions, replacing the file.
+pub(crate) fn write_manifest(path: &Path, versions: &BTreeMap<String, String>) -> Result<(), BrewverError> {
+    let contents = toml::to_string(versions)
+        .map_err(|e| BrewverError::InvalidManifest { path: path.to_path_buf(), reason: e.to_string() })?;
+    std::fs::write(path, format!("# Written by brewver snapshot, restore with brewver sync.
+
+{}", contents))?;
+    Ok(())
+}
+
+/// Sets the version of a formula in a manifest, a `.brew-versions` file or a Brewfile, which is
+/// told by its name, replacing the line that lists the formula or else appending one. The other
+/// lines, comments included, are left as they are.
+pub(crate) fn record_version(path: &Path, name: &str, formula: &Formula) -> Result<(), BrewverError> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let file_name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+    let (brewfile, version_file) = (file_name.ends_with("Brewfile"), file_name == VERSION_FILE);
+    let entry = if brewfile {
+        formula.brewfile_entry()
+    } else if version_file {
+        format!("{} {}", name, formula.version)
+    } else {
+        format!("{} = \"{}\"", toml_key(name), formula.version)
+    };
+    let lists = |line: &str| if brewfile {
+        brewfile_lists(line, &formula.brew_name)
+    } else if version_file {
+        line.split_whitespace().next() == Some(name)
+    } else {
+        manifest_key(line) == Some(name)
+    };
+
+    let mut lines: Vec<String> = contents.lines().map(String::from).collect();
+    match lines.iter_mut().find(|line| lists(line)) {
+        Some(line) => *line = entry,
+        None => lines.push(entry),
+    }
+    std::fs::write(path, format!("{}\n", lines.join("\n")))?;
+    Ok(())
+}
+
+/// Whether a Brewfile line installs the formula, with or without a version after its name.
+fn brewfile_lists(line: &str, brew_name: &str) -> bool {
+    let Some(spec) = line.trim().strip_prefix("brew \"").or_else(|| line.trim().strip_prefix("cask \"")).and_then(|rest| rest.split('"').next()) else {
+        return false;
+    };
+    // A version has a dot, which tells `openssl@3.1.4` apart from the formula `openssl@3`.
+    spec == brew_name || spec.strip_prefix(brew_name).and_then(|rest| rest.strip_prefix('@')).is_some_and(|version| version.contains('.'))
+}
+
+/// The formula a manifest line sets the version of.
+fn manifest_key(line: &str) -> Option<&str> {
+    let (key, _) = line.split_once('=')?;
+    Some(key.trim().trim_matches('"'))
+}
+
+/// The name as a TOML key, quoted unless it is a bare key.
+fn toml_key(name: &str) -> String {
+    if name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        name.to_string()
+    } else {
+        format!("\"{}\"", name)
+    }
+}
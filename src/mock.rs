@@ -0,0 +1,201 @@
+//! In-memory stand-ins for GitHub and brew, so the resolution, download and install steps can
+//! be tested without network access or a Homebrew installation:
+//!
+//! ```
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use brewver::mock::{MockBrew, MockClient};
+//!
+//! let commits = r#"[{"sha": "abc123", "commit": {"message": "wget: update 1.21.3 bottle."}}]"#;
+//! let client = MockClient::new()
+//!     .respond("https://api.github.com/repos/Homebrew/homebrew-core/commits?path=/Formula/w/wget.rb&per_page=100", 200, commits)
+//!     .respond("https://raw.githubusercontent.com/Homebrew/homebrew-core/abc123/Formula/w/wget.rb", 200, "class Wget < Formula\nend\n");
+//! let formula = brewver::FormulaResolver::new(&client).resolve("wget", "1.21.3")?;
+//! let file = formula.download(&client)?;
+//!
+//! let brew = MockBrew::new();
+//! brewver::Installer::new().brew(brew.clone()).install(&formula, &file)?;
+//! assert_eq!(brew.commands().last().map(|command| command.starts_with("install ")), Some(true));
+//! # Ok(())
+//! # }
+//! ```
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::cache::Cache;
+use crate::client::{GithubClient, Response};
+use crate::error::{BrewverError, Result};
+use crate::github::{DEFAULT_API_URL, DEFAULT_RAW_URL};
+use crate::installer::BrewRunner;
+
+/// Answers requests with the responses registered for their URLs, and everything else with a 404
+/// like GitHub's.
+#[derive(Default)]
+pub struct MockClient {
+    responses: HashMap<String, Response>,
+    requests: RefCell<Vec<String>>,
+    token: bool,
+    offline: bool,
+    cache: Option<Cache>,
+}
+
+impl MockClient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answers requests for the URL with the status and body.
+    pub fn respond(self, url: &str, status: u16, body: impl Into<String>) -> Self {
+        self.respond_with(Response { url: url.to_string(), status, headers: BTreeMap::new(), body: body.into() })
+    }
+
+    /// Answers requests for the URL of the response with it, headers included.
+    pub fn respond_with(mut self, response: Response) -> Self {
+        self.responses.insert(response.url.clone(), response);
+        self
+    }
+
+    /// Acts as if a GitHub token was configured.
+    pub fn token(mut self, token: bool) -> Self {
+        self.token = token;
+        self
+    }
+
+    /// Acts as if the client was built with [`crate::ClientBuilder::offline`].
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Keeps resolved commits and downloaded formula files in the cache, like
+    /// [`crate::ClientBuilder::cache`].
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// The URLs requested so far, in order.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.borrow().clone()
+    }
+
+    fn answer(&self, url: &str) -> Result<Response> {
+        if self.offline {
+            return Err(BrewverError::Offline { url: url.to_string() });
+        }
+        self.requests.borrow_mut().push(url.to_string());
+        Ok(self.responses.get(url).cloned().unwrap_or_else(|| Response {
+            url: url.to_string(),
+            status: 404,
+            headers: BTreeMap::new(),
+            body: r#"{"message":"Not Found"}"#.to_string(),
+        }))
+    }
+}
+
+impl GithubClient for MockClient {
+    fn get(&self, url: &str) -> Result<Response> {
+        self.answer(url)
+    }
+
+    fn get_all(&self, urls: &[String]) -> Vec<Result<Response>> {
+        urls.iter().map(|url| self.answer(url)).collect()
+    }
+
+    fn download(&self, url: &str) -> Result<Response> {
+        self.answer(url)
+    }
+
+    fn download_file(&self, url: &str, path: &Path) -> Result<()> {
+        let response = self.answer(url)?;
+        if !response.is_success() {
+            return Err(BrewverError::HttpStatus { url: response.url, status: response.status });
+        }
+        std::fs::write(path, response.body)?;
+        Ok(())
+    }
+
+    fn post(&self, url: &str, _body: &serde_json::Value) -> Result<Response> {
+        self.answer(url)
+    }
+
+    fn api_url(&self) -> &str {
+        DEFAULT_API_URL
+    }
+
+    fn raw_url(&self) -> &str {
+        DEFAULT_RAW_URL
+    }
+
+    fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    fn has_token(&self) -> bool {
+        self.token
+    }
+
+    fn cache(&self) -> Option<&Cache> {
+        self.cache.as_ref()
+    }
+}
+
+/// Records the brew commands it is asked to run instead of running them. Commands succeed with
+/// no output unless an output or a failure was registered for them. Clones share the record, so
+/// a clone can be handed to an [`crate::Installer`] and the original inspected afterwards.
+#[derive(Clone, Default)]
+pub struct MockBrew {
+    /// The stdout of the commands starting with each prefix, or `None` when they fail.
+    outputs: Vec<(String, Option<String>)>,
+    commands: Rc<RefCell<Vec<String>>>,
+}
+
+impl MockBrew {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Prints the output for commands starting with the given arguments, e.g. `list --versions wget`.
+    pub fn respond(mut self, command: &str, stdout: &str) -> Self {
+        self.outputs.push((command.to_string(), Some(stdout.to_string())));
+        self
+    }
+
+    /// Fails commands starting with the given arguments.
+    pub fn fail(mut self, command: &str) -> Self {
+        self.outputs.push((command.to_string(), None));
+        self
+    }
+
+    /// The commands run so far without the leading `brew`, in order.
+    pub fn commands(&self) -> Vec<String> {
+        self.commands.borrow().clone()
+    }
+
+    fn answer(&self, args: &[&str]) -> Result<String> {
+        let command = args.join(" ");
+        self.commands.borrow_mut().push(command.clone());
+        match self.outputs.iter().rev().find(|(prefix, _)| command.starts_with(prefix.as_str())) {
+            Some((_, Some(stdout))) => Ok(stdout.clone()),
+            Some((_, None)) => Err(BrewverError::BrewFailed {
+                command: format!("brew {}", command),
+                status: "exit status: 1".to_string(),
+                code: Some(1),
+                stderr: String::new(),
+            }),
+            None => Ok(String::new()),
+        }
+    }
+}
+
+impl BrewRunner for MockBrew {
+    fn output(&self, args: &[&str]) -> Result<String> {
+        self.answer(args)
+    }
+
+    fn run(&self, args: &[&str], _quiet: bool) -> Result<()> {
+        self.answer(args).map(|_| ())
+    }
+}
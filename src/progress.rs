@@ -0,0 +1,81 @@
+//! Progress events for tools embedding brewver, such as a GUI drawing its own progress instead of
+//! reading brewver's log.
+//!
+//! ```no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let (sender, events) = std::sync::mpsc::channel();
+//! brewver::set_progress(move |event| {
+//!     let _ = sender.send(event.clone());
+//! });
+//! std::thread::spawn(move || {
+//!     for event in events {
+//!         println!("{:?}", event);
+//!     }
+//! });
+//!
+//! let client = brewver::Client::new();
+//! let formula = brewver::FormulaResolver::new(&client).resolve("wget", "1.21.3")?;
+//! let file = formula.download(&client)?;
+//! brewver::Installer::new().install(&formula, &file)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::sync::{Arc, OnceLock};
+
+use crate::error::Result;
+use crate::formula::Formula;
+
+/// What brewver is doing, as reported to the callback of [`set_progress`].
+///
+/// As JSON, the event is an object whose `phase` tells which one it is, such as
+/// `{"phase":"download","url":"...","bytes":1234,"total":5678}`.
+#[derive(serde::Serialize, schemars::JsonSchema, Clone, Debug, PartialEq)]
+#[serde(tag = "phase", rename_all = "snake_case")]
+pub enum ProgressEvent {
+    /// [`crate::FormulaResolver::resolve`] started looking up a version.
+    #[serde(rename = "resolve")]
+    ResolveStarted { name: String, version: String },
+    /// The commit of the formula file of a version was found.
+    #[serde(rename = "commit")]
+    CommitFound { name: String, version: String, repo: String, commit: String },
+    /// Part of a formula file or bottle arrived, out of the total size when the server tells it.
+    #[serde(rename = "download")]
+    DownloadProgress {
+        url: String,
+        #[serde(rename = "bytes")]
+        received: u64,
+        total: Option<u64>,
+    },
+    /// A line brew printed while changing the installation.
+    #[serde(rename = "install_output")]
+    InstallOutputLine { line: String },
+    /// [`crate::Installer::install`] or [`crate::Installer::install_bottle`] finished, with the
+    /// error it failed with, if any.
+    Finished { name: String, version: String, error: Option<String> },
+}
+
+/// Receives the progress events, from whichever thread they happen on.
+pub type Progress = Arc<dyn Fn(&ProgressEvent) + Send + Sync>;
+
+static PROGRESS: OnceLock<Progress> = OnceLock::new();
+
+/// Reports the progress of all resolutions, downloads and installs of the process to the
+/// callback. Only the first callback set is used.
+pub fn set_progress(progress: impl Fn(&ProgressEvent) + Send + Sync + 'static) {
+    let _ = PROGRESS.set(Arc::new(progress));
+}
+
+pub(crate) fn report(event: impl FnOnce() -> ProgressEvent) {
+    if let Some(progress) = PROGRESS.get() {
+        progress(&event());
+    }
+}
+
+pub(crate) fn report_finished<T>(formula: &Formula, result: &Result<T>) {
+    report(|| ProgressEvent::Finished {
+        name: formula.brew_name.clone(),
+        version: formula.version.clone(),
+        error: result.as_ref().err().map(|e| e.to_string()),
+    });
+}
@@ -0,0 +1,1738 @@
+//! Finds the homebrew-core commit whose formula file installs a given version.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use log::{debug, info, warn};
+
+use crate::cache::Cache;
+use crate::backends::{BrewApiResolver, GitResolver, GraphqlResolver, RestResolver, VersionResolver};
+use crate::client::{last_page_url, next_page_url, page_number, rate_limit_remaining, with_page_number, GithubClient, Response};
+use crate::error::{BrewverError, Result};
+use crate::formula::{declared_version, upstream_version, Formula};
+use crate::git::LocalTap;
+use crate::installer::Confirm;
+use crate::progress::{self, ProgressEvent};
+use crate::stats;
+use crate::github::{
+    format_gh_api_commit_url, format_gh_api_commits_url, format_gh_api_commits_until_url, format_gh_api_compare_url, format_gh_api_contents_url, format_gh_api_raw_file_url,
+    format_gh_api_repo_url, format_gh_api_tree_url, format_gh_graphql_history_query, format_gh_graphql_url, format_brew_api_url, repo_tap, tap_repo,
+};
+
+const CORE_REPO: &str = "Homebrew/homebrew-core";
+const CASK_REPO: &str = "Homebrew/homebrew-cask";
+
+/// How many pages of a commit history are requested at once.
+const PAGE_BATCH: u32 = 4;
+
+/// How many moves of a formula file are followed back through its history.
+const MAX_RENAMES: usize = 4;
+
+/// The version that stands for the newest one in the history of a formula, as in `jq@latest`.
+pub const LATEST: &str = "latest";
+
+/// How many names close to an unknown one are suggested.
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Resolves formula versions to commits through the GitHub API.
+///
+/// ```no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let client = brewver::Client::new();
+/// let formula = brewver::FormulaResolver::new(&client)
+///     .fallback_tap(Some("user/tap".to_string()))
+///     .resolve("wget", "1.21.3")?;
+/// println!("{}", formula.url);
+/// # Ok(())
+/// # }
+/// ```
+pub struct FormulaResolver<'a> {
+    client: &'a dyn GithubClient,
+    normalize_case: bool,
+    install_name: Option<String>,
+    fallback_tap: Option<String>,
+    core_tag: Option<String>,
+    commit: Option<String>,
+    as_of: Option<String>,
+    max_pages: u32,
+    verify_default_branch: bool,
+    source: Source,
+    cask: bool,
+    tap: Option<String>,
+    fuzzy: bool,
+    backend: Backend,
+    revision: Option<u32>,
+    n_back: usize,
+    match_strategy: MatchStrategy,
+    match_upstream_url: bool,
+    fetch_clone: Option<Confirm>,
+    chain: Vec<Box<dyn VersionResolver>>,
+    /// The pages of commit histories read so far, by URL or GraphQL query, so that resolving
+    /// several versions of a formula reads its history once.
+    history_pages: RefCell<HashMap<String, Response>>,
+    /// The decisions taken while resolving, for [`Self::trail`].
+    trail: RefCell<Vec<String>>,
+}
+
+/// Where the resolver reads formula history from.
+#[derive(clap_derive::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Source {
+    /// The GitHub API, switching to the local clone when the rate limit is hit
+    #[default]
+    Auto,
+    /// Only the GitHub API
+    Api,
+    /// Only the local clone of the tap, found through `brew --repository`
+    Git,
+}
+
+/// How a version is mapped to the commit of its formula file.
+#[derive(clap_derive::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Backend {
+    /// Search the commit history for the bottle (or version bump) commit of the version
+    #[default]
+    Commits,
+    /// Ask the formulae.brew.sh API, which only knows the current version of each formula
+    Api,
+    /// Search the commit history like `commits`, through the GitHub GraphQL API, which needs a token
+    /// but reads only the fields it needs
+    Graphql,
+    /// Binary-search the commit history for the newest commit whose formula file declares the
+    /// version, reading the file at each probed commit. Slower, but independent of commit messages
+    Content,
+}
+
+/// Which commit messages count as the commit of a version.
+#[derive(clap_derive::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum MatchStrategy {
+    /// Only bottle commits, `<name>: update <version> bottle`
+    #[default]
+    Bottle,
+    /// Bottle commits and version bumps such as `<name> <version>`, `<name>: update to <version>`
+    /// or `<name>: bump <old> -> <version>`
+    Bump,
+    /// Bottle commits, and any commit of the formula whose file declares the version, which reads
+    /// the file at each candidate commit
+    Verified,
+}
+
+/// A version found in the bottle commits of a formula.
+pub struct FormulaVersion {
+    pub version: String,
+    pub commit: String,
+    pub date: String,
+}
+
+/// How many GitHub API requests resolving a version is likely to take, from
+/// [`FormulaResolver::estimate_requests`].
+#[derive(serde::Serialize, schemars::JsonSchema, Clone, Debug)]
+pub struct RequestEstimate {
+    pub formula: String,
+    pub version: String,
+    /// The candidate paths whose histories would be searched, with the pages of each that would
+    /// be read, up to `max_pages`.
+    pub paths: Vec<(String, u32)>,
+    /// The requests the estimate took, reading the first page of each path. Resolving reuses them.
+    pub spent: u32,
+    /// The most requests resolving takes on top of those spent, not counting the ones following
+    /// renamed files.
+    pub requests: u32,
+}
+
+impl<'a> FormulaResolver<'a> {
+    pub fn new(client: &'a dyn GithubClient) -> Self {
+        Self {
+            client,
+            normalize_case: true,
+            install_name: None,
+            fallback_tap: None,
+            core_tag: None,
+            commit: None,
+            as_of: None,
+            max_pages: 10,
+            verify_default_branch: false,
+            source: Source::Auto,
+            cask: false,
+            tap: None,
+            fuzzy: false,
+            backend: Backend::Commits,
+            revision: None,
+            n_back: 0,
+            match_strategy: MatchStrategy::Bottle,
+            match_upstream_url: false,
+            fetch_clone: None,
+            chain: Vec::new(),
+            history_pages: RefCell::new(HashMap::new()),
+            trail: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Lowercases formula names before using them. On by default.
+    pub fn normalize_case(mut self, normalize: bool) -> Self {
+        self.normalize_case = normalize;
+        self
+    }
+
+    /// The canonical name to look the formula up by, e.g. after a rename.
+    pub fn install_name(mut self, install_name: Option<String>) -> Self {
+        self.install_name = install_name;
+        self
+    }
+
+    /// A tap searched when the version is not found in homebrew-core.
+    pub fn fallback_tap(mut self, tap: Option<String>) -> Self {
+        self.fallback_tap = tap;
+        self
+    }
+
+    /// Resolves formulas as they were at this homebrew-core tag, ignoring the requested version.
+    pub fn core_tag(mut self, tag: Option<String>) -> Self {
+        self.core_tag = tag;
+        self
+    }
+
+    /// Takes formula files from this commit instead of searching for the commit of the version.
+    pub fn commit(mut self, commit: Option<String>) -> Self {
+        self.commit = commit;
+        self
+    }
+
+    /// Resolves formulas as they were at the end of this day (`YYYY-MM-DD`), ignoring the
+    /// requested version.
+    pub fn as_of(mut self, date: Option<String>) -> Self {
+        self.as_of = date;
+        self
+    }
+
+    /// The most pages of 100 commits to search per formula path. Defaults to 10.
+    pub fn max_pages(mut self, max_pages: u32) -> Self {
+        self.max_pages = max_pages;
+        self
+    }
+
+    /// Warns when the resolved commit is not on the default branch of its repository.
+    pub fn verify_default_branch(mut self, verify: bool) -> Self {
+        self.verify_default_branch = verify;
+        self
+    }
+
+    /// Where to read formula history from. Defaults to [`Source::Auto`].
+    pub fn source(mut self, source: Source) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Offers to clone the tap when it isn't cloned locally, and to fetch the older commits of a
+    /// shallow clone, before reading history from it. The tap is fetched once the confirmation
+    /// returns `Ok` and the client is online; otherwise, or without a confirmation, resolving
+    /// fails with [`BrewverError::TapNotCloned`] or [`BrewverError::ShallowClone`].
+    pub fn fetch_clone(mut self, confirm: impl Fn(&str) -> Result<()> + 'static) -> Self {
+        self.fetch_clone = Some(Rc::new(confirm));
+        self
+    }
+
+    /// Finds the commits of versions with these resolvers instead of as the backend and source
+    /// say, trying each in turn when the ones before it fail.
+    pub fn chain(mut self, chain: Vec<Box<dyn VersionResolver>>) -> Self {
+        self.chain = chain;
+        self
+    }
+
+    /// Resolves casks from homebrew-cask instead of formulas.
+    pub fn cask(mut self, cask: bool) -> Self {
+        self.cask = cask;
+        self
+    }
+
+    /// Looks formulas up in this tap instead of homebrew-core. Names qualified with a tap,
+    /// such as `hashicorp/tap/terraform`, use their own tap.
+    pub fn tap(mut self, tap: Option<String>) -> Self {
+        self.tap = tap;
+        self
+    }
+
+    /// Falls back to the newest version that starts with the requested one, e.g. `1.2.4_1` for `1.2`,
+    /// when no commit mentions the requested version exactly.
+    pub fn fuzzy(mut self, fuzzy: bool) -> Self {
+        self.fuzzy = fuzzy;
+        self
+    }
+
+    /// How versions are mapped to commits. Defaults to [`Backend::Commits`]; formulas from other
+    /// taps than homebrew-core and homebrew-cask always search the commits.
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Resolves this revision of the version, e.g. `1.2.3_2` for revision 2 of `1.2.3`, or the
+    /// version without a revision suffix for 0. By default the newest revision is used.
+    pub fn revision(mut self, revision: Option<u32>) -> Self {
+        self.revision = revision;
+        self
+    }
+
+    /// Resolves [`LATEST`] to the version this many versions before the newest one in the
+    /// history of the formula. Defaults to 0, the newest version.
+    pub fn n_back(mut self, n_back: usize) -> Self {
+        self.n_back = n_back;
+        self
+    }
+
+    /// Which commit messages count as the commit of a version. Bottle commits by default.
+    pub fn match_strategy(mut self, strategy: MatchStrategy) -> Self {
+        self.match_strategy = strategy;
+        self
+    }
+
+    /// When the formula file is read to match the version, by [`Backend::Content`] or
+    /// [`MatchStrategy::Verified`], compares the version with the one in the name of its source
+    /// archive rather than with its `version`, for formulas whose version differs from upstream's.
+    /// Off by default.
+    pub fn match_upstream_url(mut self, upstream: bool) -> Self {
+        self.match_upstream_url = upstream;
+        self
+    }
+
+    /// The version the formula file declares, or upstream's with [`Self::match_upstream_url`].
+    fn file_version(&self, contents: &str) -> Option<String> {
+        if self.match_upstream_url { upstream_version(contents) } else { declared_version(contents) }
+    }
+
+    /// The steps the resolutions so far took, in order: the paths probed and how many pages of
+    /// their histories were read, the commit that matched and why, and the fallbacks used.
+    pub fn trail(&self) -> Vec<String> {
+        self.trail.borrow().clone()
+    }
+
+    /// The client requests go through, for a [`VersionResolver`] of the chain.
+    pub fn client(&self) -> &dyn GithubClient {
+        self.client
+    }
+
+    /// The paths the file of the formula (or cask) may have been at in the repository, where its
+    /// history is searched, the current one first.
+    pub fn candidate_paths(&self, repo: &str, name: &str) -> Vec<String> {
+        self.file_paths(repo, name)
+    }
+
+    fn note(&self, step: String) {
+        debug!("{}", step);
+        self.trail.borrow_mut().push(step);
+    }
+
+    /// The name brew knows the formula by.
+    pub fn brew_name(&self, name: &str) -> String {
+        brew_name(name, self.normalize_case)
+    }
+
+    /// Finds the formula file for a version, or for the configured tag. [`LATEST`] stands for the
+    /// newest version in the history of the formula, and a pattern such as `14.*` or `>=14, <16`
+    /// for the newest version matching it, see [`is_version_pattern`].
+    pub fn resolve(&self, name: &str, version: &str) -> Result<Formula> {
+        let started = Instant::now();
+        let resolved = self.resolve_version(name, version);
+        stats::add_resolve_time(started.elapsed());
+        resolved
+    }
+
+    fn resolve_version(&self, name: &str, version: &str) -> Result<Formula> {
+        if version == LATEST {
+            let version = self.latest_version(name)?;
+            return self.resolve_version(name, &version);
+        }
+        if is_version_pattern(version) {
+            let version = self.matching_version(name, version)?;
+            return self.resolve_version(name, &version);
+        }
+        let name = if self.normalize_case { normalize_name(name) } else { name.to_string() };
+        let (tap, brew_name) = split_tap(&name);
+        check_name(&name, brew_name)?;
+        let mut brew_name = brew_name.to_string();
+        let mut repo = self.repo(tap);
+        let mut name = self.lookup_name(&brew_name);
+        let version = match self.revision {
+            Some(revision) if revision > 0 && !version.is_empty() => format!("{}_{}", version, revision),
+            _ => version.to_string(),
+        };
+        let version = version.as_str();
+        progress::report(|| ProgressEvent::ResolveStarted { name: brew_name.clone(), version: version.to_string() });
+        let backend = if self.chain.is_empty() {
+            format!("the {} backend", format!("{:?}", self.backend).to_lowercase())
+        } else {
+            format!("the {} resolvers", self.chain.iter().map(|resolver| resolver.name()).collect::<Vec<_>>().join(", "))
+        };
+        let strategy = format!("{:?}", self.match_strategy).to_lowercase();
+        self.note(format!("Resolving {}@{} in {} with {} and the {} match strategy", name, version, repo, backend, strategy));
+
+        let mut located = self.locate_nearest(&repo, &name, version);
+        let not_found = matches!(located, Err(BrewverError::CommitNotFound { .. } | BrewverError::FileNotFound { .. }));
+        if not_found && self.install_name.is_none() && !is_tap(&repo) {
+            match self.canonical_name(&repo, &name) {
+                Ok(Some(canonical)) => {
+                    info!("{} is called {} in {}, looking that up instead", name, canonical, repo);
+                    self.note(format!("Nothing was found for {}, which formula_renames.json or Aliases map to {}; looking that up instead", name, canonical));
+                    located = self.locate_nearest(&repo, &canonical, version);
+                    name = canonical.clone();
+                    brew_name = canonical;
+                }
+                Ok(None) => {}
+                Err(e) => warn!("Failed to look up renames and aliases of {}: {}", name, e),
+            }
+        }
+
+        let (commit, path, version) = match located {
+            Ok(found) => found,
+            Err(e) if self.git_ref().is_none() => {
+                let Some(tap) = &self.fallback_tap else {
+                    return Err(self.explain_not_found(&repo, &name, e));
+                };
+                warn!("Lookup in {} failed: {}, trying {}", repo, e, tap);
+                self.note(format!("The lookup in {} failed ({}), falling back to the tap {}", repo, e, tap));
+                repo = tap_repo(tap);
+                let found = self.locate_nearest(&repo, &name, version)?;
+                info!("{}@{} was found in {}", name, version, tap);
+                found
+            }
+            Err(e) => return Err(self.explain_not_found(&repo, &name, e)),
+        };
+
+        let formula = Formula {
+            url: format_gh_api_raw_file_url(self.client.raw_url(), &repo, &commit, &path),
+            cask: self.cask,
+            name,
+            brew_name,
+            version,
+            repo,
+            path,
+            commit,
+        };
+        if self.verify_default_branch {
+            self.verify_commit(&formula)?;
+        }
+        progress::report(|| ProgressEvent::CommitFound {
+            name: formula.brew_name.clone(),
+            version: formula.version.clone(),
+            repo: formula.repo.clone(),
+            commit: formula.commit.clone(),
+        });
+        Ok(formula)
+    }
+
+    /// Finds the file of a dependency at the commit the dependent formula was resolved to.
+    pub fn resolve_dependency(&self, name: &str, dependent: &Formula) -> Result<Formula> {
+        self.resolve_at(name, "", &dependent.repo, &dependent.commit)
+    }
+
+    /// Finds the file of a formula at a known commit of a repository, such as the one a version
+    /// was installed from.
+    pub fn resolve_at(&self, name: &str, version: &str, repo: &str, commit: &str) -> Result<Formula> {
+        check_name(name, name)?;
+        let started = Instant::now();
+        let path = self.with_source(
+            || self.find_tagged_file(repo, name, commit),
+            || self.find_tagged_file_in_clone(repo, name, commit),
+        );
+        stats::add_resolve_time(started.elapsed());
+        let path = path?;
+
+        Ok(Formula {
+            name: name.to_string(),
+            brew_name: name.to_string(),
+            version: version.to_string(),
+            repo: repo.to_string(),
+            url: format_gh_api_raw_file_url(self.client.raw_url(), repo, commit, &path),
+            path,
+            commit: commit.to_string(),
+            cask: false,
+        })
+    }
+
+    /// Lists the versions mentioned in bottle commits of every candidate path, newest first.
+    pub fn versions(&self, name: &str) -> Result<Vec<FormulaVersion>> {
+        let name = if self.normalize_case { normalize_name(name) } else { name.to_string() };
+        let (tap, brew_name) = split_tap(&name);
+        check_name(&name, brew_name)?;
+        let repo = self.repo(tap);
+        let name = self.lookup_name(brew_name);
+        self.with_source(|| self.find_versions(&repo, &name), || self.find_versions_in_clone(&repo, &name))
+    }
+
+    /// The newest version in the history of the formula, or the one `n_back` versions before it.
+    pub fn latest_version(&self, name: &str) -> Result<String> {
+        let versions = self.versions(name)?;
+        let Some(latest) = versions.get(self.n_back) else {
+            return Err(BrewverError::NotEnoughVersions { name: name.to_string(), found: versions.len(), n_back: self.n_back });
+        };
+        info!("The {} version of {} is {}", if self.n_back == 0 { "latest".to_string() } else { format!("{} versions back", self.n_back) }, name, latest.version);
+        Ok(latest.version.clone())
+    }
+
+    /// The newest version in the history of the formula that matches a pattern from
+    /// [`is_version_pattern`].
+    pub fn matching_version(&self, name: &str, pattern: &str) -> Result<String> {
+        let versions = self.versions(name)?;
+        let Some(newest) = versions.iter()
+            .filter(|v| matches_pattern(pattern, &v.version))
+            .max_by(|a, b| version_key(&a.version).cmp(&version_key(&b.version))) else {
+            return Err(BrewverError::NoMatchingVersion { name: name.to_string(), pattern: pattern.to_string() });
+        };
+        info!("The newest version of {} matching {} is {}", name, pattern, newest.version);
+        self.note(format!("Took {}, the newest of the {} versions in the history that matches {}", newest.version, versions.len(), pattern));
+        Ok(newest.version.clone())
+    }
+
+    /// Estimates the GitHub API requests resolving the version takes, from the number of pages in
+    /// the history of each candidate path, which the first page of each tells. A search stops at
+    /// the path whose first page has the commit of the version. Lookups that don't search the
+    /// history, such as with a cached commit, [`Backend::Api`] or [`Source::Git`], take none or
+    /// one per path.
+    pub fn estimate_requests(&self, name: &str, version: &str) -> Result<RequestEstimate> {
+        let name = if self.normalize_case { normalize_name(name) } else { name.to_string() };
+        let (tap, brew_name) = split_tap(&name);
+        check_name(&name, brew_name)?;
+        let repo = self.repo(tap);
+        let name = self.lookup_name(brew_name);
+        let mut estimate = RequestEstimate { formula: name.clone(), version: version.to_string(), paths: Vec::new(), spent: 0, requests: 0 };
+        let file_paths = self.file_paths(&repo, &name);
+        if self.client.is_offline() || self.source == Source::Git {
+            return Ok(estimate);
+        }
+        if self.git_ref().is_some() || self.as_of.is_some() {
+            estimate.requests = file_paths.len() as u32;
+            return Ok(estimate);
+        }
+        let searches = version == LATEST || is_version_pattern(version);
+        let cached = self.client.cache().and_then(|cache| cache.commit(&repo, &name, &self.commit_key(version))).is_some();
+        if !searches && (cached || (self.backend == Backend::Api && !is_tap(&repo))) {
+            return Ok(estimate);
+        }
+        if self.max_pages == 0 {
+            return Ok(estimate);
+        }
+
+        let urls: Vec<String> = file_paths.iter()
+            .map(|file_path| format_gh_api_commits_url(self.client.api_url(), &repo, file_path))
+            .collect();
+        let mut found = false;
+        for (file_path, response) in file_paths.iter().zip(self.get_history_pages(&urls)) {
+            let response = response?;
+            estimate.spent += 1;
+            if found {
+                continue;
+            }
+            found = !searches && visit_page(&response, &mut |commit| {
+                let message = commit.pointer("/commit/message").and_then(|m| m.as_str()).unwrap_or_default();
+                self.is_version_commit(&repo, &name, version, message)
+            })?.is_some();
+            let pages = if found { 1 } else { last_page_url(&response).and_then(|url| page_number(&url)).unwrap_or(1).min(self.max_pages) };
+            estimate.paths.push((file_path.clone(), pages));
+            estimate.requests += pages - 1;
+        }
+        Ok(estimate)
+    }
+
+    /// The date the commit (or tag) of a resolved formula was committed.
+    pub fn commit_date(&self, formula: &Formula) -> Result<String> {
+        self.with_source(
+            || {
+                let url = format_gh_api_commit_url(self.client.api_url(), &formula.repo, &formula.commit);
+                let commit = self.client.get(&url)?.api_json()?;
+                commit.pointer("/commit/committer/date")
+                    .and_then(|d| d.as_str())
+                    .map(String::from)
+                    .ok_or(BrewverError::UnexpectedResponse { url, reason: "no commit date".to_string() })
+            },
+            || self.local_history(&formula.repo)?.commit_date(&formula.commit),
+        )
+    }
+
+    /// An official versioned formula covering the version, such as `node@18` for `18.17.1` or
+    /// `python@3.10` for `3.10.4`, as listed by the Homebrew API.
+    pub fn versioned_formula(&self, name: &str, version: &str) -> Result<Option<String>> {
+        let brew_name = self.brew_name(name);
+        if self.cask || self.tap.is_some() || split_tap(name).0.is_some() || brew_name.contains('@') || self.client.is_offline() {
+            return Ok(None);
+        }
+        if !version.starts_with(|c: char| c.is_ascii_digit()) {
+            return Ok(None);
+        }
+
+        let parts: Vec<&str> = version.split('.').collect();
+        let mut candidates: Vec<String> = [2, 1].iter().filter(|len| parts.len() > **len).map(|len| parts[..*len].join(".")).collect();
+        candidates.dedup();
+        for candidate in candidates {
+            let versioned = format!("{}@{}", brew_name, candidate);
+            let url = format_brew_api_url(&versioned, false);
+            debug!("URL: {:?}", &url);
+            if self.client.get(&url)?.is_success() {
+                return Ok(Some(versioned));
+            }
+        }
+        Ok(None)
+    }
+
+    fn lookup_name(&self, brew_name: &str) -> String {
+        match &self.install_name {
+            Some(install_name) if install_name != brew_name => {
+                info!("Looking up {} as {}", brew_name, install_name);
+                self.note(format!("Looking up {} as {}, as --install-name asks", brew_name, install_name));
+                install_name.clone()
+            }
+            _ => brew_name.to_string(),
+        }
+    }
+
+    /// Like [`Self::locate`], but with [`Self::fuzzy`] set, a version that isn't found is replaced by
+    /// its nearest match. Also returns the version that was located.
+    fn locate_nearest(&self, repo: &str, name: &str, version: &str) -> Result<(String, String, String)> {
+        match self.locate(repo, name, version) {
+            Ok((commit, path)) => Ok((commit, path, version.to_string())),
+            Err(e @ BrewverError::CommitNotFound { .. }) if self.fuzzy => {
+                let versions = self.with_source(|| self.find_versions(repo, name), || self.find_versions_in_clone(repo, name))?;
+                let Some(nearest) = nearest_version(version, &versions) else {
+                    self.note(format!("{} was not found, and --fuzzy found no version starting with it", version));
+                    return Err(e);
+                };
+                info!("{}@{} was not found, using the nearest match {}", name, version, nearest.version);
+                self.note(format!("{} was not found, and --fuzzy took the nearest version {}", version, nearest.version));
+                let (commit, path) = self.locate(repo, name, &nearest.version)?;
+                Ok((commit, path, nearest.version.clone()))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The commit or tag formula files are taken from without searching the history.
+    fn git_ref(&self) -> Option<&String> {
+        self.commit.as_ref().or(self.core_tag.as_ref())
+    }
+
+    /// The current name of a formula that was renamed (following `formula_renames.json`, or
+    /// `cask_renames.json` for casks), or whose name is an alias in `Aliases`.
+    fn canonical_name(&self, repo: &str, name: &str) -> Result<Option<String>> {
+        let renames_path = if self.cask { "/cask_renames.json" } else { "/formula_renames.json" };
+        let renames: HashMap<String, String> = self.head_file(repo, renames_path)?
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default();
+        let mut current = name;
+        for _ in 0..10 {
+            match renames.get(current) {
+                Some(new_name) if new_name != name => current = new_name,
+                _ => break,
+            }
+        }
+        if current != name {
+            return Ok(Some(current.to_string()));
+        }
+        if self.cask {
+            return Ok(None);
+        }
+
+        // Aliases are symlinks, whose contents are the path they point at.
+        let alias = self.head_file(repo, &format!("/Aliases/{}", name))?;
+        Ok(alias.and_then(|target| Path::new(target.trim()).file_stem()?.to_str().map(String::from)))
+    }
+
+    /// Turns a lookup that found nothing into [`BrewverError::UnknownFormula`], with the names
+    /// closest to it, when no file of the name ever was in the repository, as happens with typos.
+    fn explain_not_found(&self, repo: &str, name: &str, e: BrewverError) -> BrewverError {
+        if !matches!(e, BrewverError::CommitNotFound { .. } | BrewverError::FileNotFound { .. }) || self.max_pages == 0 {
+            return e;
+        }
+        self.note(format!("Checking whether {} ever had a file for {}", repo, name));
+        let has_history = self.with_source(
+            || Ok(self.walk_file_histories(repo, name, self.backend == Backend::Graphql, |_, _| true)?.is_some()),
+            || {
+                let tap = LocalTap::open(repo)?;
+                for file_path in self.file_paths(repo, name) {
+                    if !tap.commits(&file_path)?.is_empty() {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            },
+        );
+        match has_history {
+            Ok(false) => BrewverError::UnknownFormula { name: name.to_string(), repo: repo.to_string(), suggestions: self.similar_names(repo, name) },
+            Ok(true) => e,
+            Err(history_error) => {
+                debug!("Failed to look for the history of {}: {}", name, history_error);
+                e
+            }
+        }
+    }
+
+    /// The formulas (or casks) of the repository whose names are closest to the name, closest
+    /// first, from the file tree of its default branch.
+    fn similar_names(&self, repo: &str, name: &str) -> Vec<String> {
+        let files = self.with_source(
+            || {
+                let url = format_gh_api_tree_url(self.client.api_url(), repo);
+                debug!("URL: {:?}", &url);
+                let tree = self.client.get(&url)?.api_json()?;
+                Ok(tree.get("tree").and_then(|tree| tree.as_array()).into_iter().flatten()
+                    .filter_map(|entry| entry.get("path")?.as_str().map(String::from))
+                    .collect::<Vec<String>>())
+            },
+            || LocalTap::open(repo)?.files(),
+        );
+        let files = match files {
+            Ok(files) => files,
+            Err(e) => {
+                debug!("Failed to list the formulas of {}: {}", repo, e);
+                return Vec::new();
+            }
+        };
+        // Formulas live where the name would, or in the one-letter shard of another name.
+        let file_paths = self.file_paths(repo, name);
+        let dirs: Vec<&str> = file_paths.iter().filter_map(|path| path.rsplit_once('/').map(|(dir, _)| dir.trim_start_matches('/'))).collect();
+        let in_dirs = |dir: &str| dirs.contains(&dir) || dir.rsplit_once('/').is_some_and(|(parent, shard)| shard.chars().count() == 1 && dirs.contains(&parent));
+        let mut candidates: Vec<(usize, String)> = files.iter()
+            .filter_map(|file| {
+                let (dir, file_name) = file.rsplit_once('/').unwrap_or(("", file));
+                Some(file_name.strip_suffix(".rb")?).filter(|_| in_dirs(dir))
+            })
+            .map(|candidate| (edit_distance(name, candidate), candidate.to_string()))
+            .filter(|(distance, _)| *distance > 0 && *distance <= (name.chars().count() / 4).max(2))
+            .collect();
+        candidates.sort();
+        candidates.dedup_by(|a, b| a.1 == b.1);
+        candidates.into_iter().take(MAX_SUGGESTIONS).map(|(_, candidate)| candidate).collect()
+    }
+
+    /// A file on the default branch of the repository, if it exists.
+    fn head_file(&self, repo: &str, file_path: &str) -> Result<Option<String>> {
+        self.with_source(
+            || {
+                let response = self.client.download(&format_gh_api_raw_file_url(self.client.raw_url(), repo, "HEAD", file_path))?;
+                match response.status {
+                    404 => Ok(None),
+                    _ if response.is_success() => Ok(Some(response.body)),
+                    status => Err(BrewverError::HttpStatus { url: response.url, status }),
+                }
+            },
+            || Ok(LocalTap::open(repo)?.show("HEAD", file_path)),
+        )
+    }
+
+    /// Returns the commit (or tag) and the path of the formula file in the repository.
+    fn locate(&self, repo: &str, name: &str, version: &str) -> Result<(String, String)> {
+        match self.git_ref() {
+            Some(tag) => {
+                self.note(format!("Taking the file at {} without searching the history, for --commit or --core-tag", tag));
+                self.with_source(
+                    || self.find_tagged_file(repo, name, tag),
+                    || self.find_tagged_file_in_clone(repo, name, tag),
+                ).map(|path| (tag.clone(), path))
+            }
+            None if self.as_of.is_some() => {
+                let until = format!("{}T23:59:59Z", self.as_of.as_deref().unwrap_or_default());
+                self.note(format!("Taking the newest commit until {}, for --as-of, whatever its version", until));
+                self.with_source(
+                    || self.find_commit_until(repo, name, &until),
+                    || self.find_commit_until_in_clone(repo, name, &until),
+                )
+            }
+            None => {
+                let key = self.commit_key(version);
+                let cached = |cache: &Cache| {
+                    let (commit, path) = cache.commit(repo, name, &key)?;
+                    info!(commit = commit.as_str(); "Found Commit: {} (cached)", commit);
+                    self.note(format!("Took commit {} at {} from the cache, where an earlier run stored it", commit, path));
+                    Some((commit, path))
+                };
+                if let Some(found) = self.client.cache().filter(|cache| cache.reads_first()).and_then(cached) {
+                    return Ok(found);
+                }
+
+                let graphql = self.backend == Backend::Graphql;
+                let found = match self.backend {
+                    _ if !self.chain.is_empty() => self.find_commit_in_chain(repo, name, version),
+                    Backend::Api if !is_tap(repo) => BrewApiResolver.find_commit(self, repo, name, version),
+                    Backend::Content => self.with_source(
+                        || self.find_commit_by_content(repo, name, version),
+                        || self.find_commit_by_content_in_clone(repo, name, version),
+                    ),
+                    _ => self.with_source(
+                        || if graphql { GraphqlResolver.find_commit(self, repo, name, version) } else { RestResolver.find_commit(self, repo, name, version) },
+                        || GitResolver.find_commit(self, repo, name, version),
+                    ),
+                };
+                let (commit, path) = match found {
+                    Err(e) if e.is_network() => {
+                        let fallback = self.client.cache().filter(|cache| cache.reads_on_failure()).and_then(cached);
+                        return fallback.ok_or(e);
+                    }
+                    found => found?,
+                };
+                if let Some(cache) = self.client.cache() {
+                    if let Err(e) = cache.store_commit(repo, name, &key, &commit, &path) {
+                        warn!("Failed to cache the commit: {}", e);
+                    }
+                }
+                Ok((commit, path))
+            }
+        }
+    }
+
+    /// The key the commit of a version is cached under. Lookups that can find another commit for
+    /// the same version, such as of an exact revision, by another match strategy, by the content
+    /// of the file or by upstream's version, are kept apart from the default one; the other
+    /// backends find the same commit.
+    fn commit_key(&self, version: &str) -> String {
+        let mut key = version.to_string();
+        if let Some(revision) = self.revision {
+            key.push_str(&format!("~revision-{}", revision));
+        }
+        match self.match_strategy {
+            MatchStrategy::Bottle => {}
+            MatchStrategy::Bump => key.push_str("~bump"),
+            MatchStrategy::Verified => key.push_str("~verified"),
+        }
+        if self.backend == Backend::Content {
+            key.push_str("~content");
+        }
+        if self.match_upstream_url {
+            key.push_str("~upstream");
+        }
+        key
+    }
+
+    /// The local clone of the repository with its full history, cloned or unshallowed first when
+    /// [`FormulaResolver::fetch_clone`] confirms it.
+    fn local_history(&self, repo: &str) -> Result<LocalTap> {
+        // Offline runs can't fetch, so they fail with how to fix the clone instead.
+        let confirmed = |question: String| !self.client.is_offline() && self.fetch_clone.as_ref().is_some_and(|confirm| confirm(&question).is_ok());
+        let tap = match LocalTap::open(repo) {
+            Err(BrewverError::TapNotCloned { tap, path }) => {
+                if !confirmed(format!("{} is not cloned locally. Clone it with `brew tap --force {}`?", tap, tap)) {
+                    return Err(BrewverError::TapNotCloned { tap, path });
+                }
+                info!("Cloning {}, which takes a while", tap);
+                self.note(format!("Cloned {}, which wasn't cloned locally", tap));
+                LocalTap::clone(&tap)?;
+                LocalTap::open(repo)?
+            }
+            tap => tap?,
+        };
+        if tap.is_shallow() {
+            let name = repo_tap(repo);
+            if !confirmed(format!("{} is a shallow clone without the older commits. Fetch them with `git fetch --unshallow`?", name)) {
+                return Err(BrewverError::ShallowClone { tap: name, path: tap.path().to_path_buf() });
+            }
+            info!("Fetching the older commits of {}, which takes a while", name);
+            self.note(format!("Fetched the older commits of {}, a shallow clone", name));
+            tap.unshallow()?;
+        }
+        Ok(tap)
+    }
+
+    /// Runs the lookup against the configured source. With [`Source::Auto`] the local clone
+    /// is only used once the GitHub API is rate limited, and offline clients only use it.
+    fn with_source<T>(
+        &self,
+        api: impl FnOnce() -> Result<T>,
+        git: impl FnOnce() -> Result<T>,
+    ) -> Result<T> {
+        if self.client.is_offline() {
+            self.note("Reading the local clone, as the run is offline".to_string());
+            return git();
+        }
+        match self.source {
+            Source::Api => api(),
+            Source::Git => {
+                self.note("Reading the local clone, as --source git asks".to_string());
+                git()
+            }
+            Source::Auto => api().or_else(|e| {
+                if !matches!(e, BrewverError::RateLimited { .. } | BrewverError::RateLimitInsufficient { .. }) {
+                    return Err(e);
+                }
+                warn!("{}, using the local clone instead", e);
+                self.note(format!("Falling back to the local clone: {}", e));
+                git()
+            }),
+        }
+    }
+
+    /// Tries the resolvers of the chain in turn until one finds the commit. When none does, fails
+    /// with the error of the first one that didn't know the version, else of the last one.
+    fn find_commit_in_chain(&self, repo: &str, name: &str, version: &str) -> Result<(String, String)> {
+        let mut not_found = None;
+        let mut last_error = None;
+        for (index, resolver) in self.chain.iter().enumerate() {
+            self.note(format!("Asking the {} resolver", resolver.name()));
+            match resolver.find_commit(self, repo, name, version) {
+                Ok(found) => return Ok(found),
+                Err(e) => {
+                    if let Some(next) = self.chain.get(index + 1) {
+                        info!("The {} resolver failed: {}, trying {}", resolver.name(), e, next.name());
+                    }
+                    self.note(format!("The {} resolver failed: {}", resolver.name(), e));
+                    match e {
+                        BrewverError::CommitNotFound { .. } if not_found.is_none() => not_found = Some(e),
+                        e => last_error = Some(e),
+                    }
+                }
+            }
+        }
+        Err(not_found.or(last_error).unwrap_or_else(|| BrewverError::CommitNotFound {
+            name: name.to_string(),
+            version: version.to_string(),
+            repo: repo.to_string(),
+            candidates: self.file_paths(repo, name),
+        }))
+    }
+
+    /// Searches the commit history through the GitHub API, the GraphQL one if asked and there is a
+    /// token, for the commit of the version.
+    pub(crate) fn find_commit(&self, repo: &str, name: &str, version: &str, graphql: bool) -> Result<(String, String)> {
+        info!("Looking for {}@{} in {}", name, version, repo);
+
+        let found = self.walk_file_histories(repo, name, graphql, |file_path, commit| {
+            let message = commit.pointer("/commit/message").and_then(|m| m.as_str()).unwrap_or_default();
+            self.matches_commit(repo, name, version, message, || {
+                let sha = commit.get("sha").and_then(|s| s.as_str()).unwrap_or_default();
+                let url = format_gh_api_raw_file_url(self.client.raw_url(), repo, sha, file_path);
+                self.client.download(&url).ok().filter(|response| response.is_success()).map(|response| response.body)
+            })
+        })?;
+        if let Some((file_path, commit)) = found {
+            let sha = commit.get("sha").and_then(|s| s.as_str()).unwrap_or_default();
+            let message = commit.pointer("/commit/message").and_then(|m| m.as_str()).unwrap_or_default();
+            self.log_revision(name, version, message);
+            self.note_match(repo, name, version, sha, &file_path, message);
+            info!(commit = sha; "Found Commit: {}", sha);
+            return Ok((sha.to_string(), file_path));
+        }
+        Err(BrewverError::CommitNotFound {
+            name: name.to_string(),
+            version: version.to_string(),
+            repo: repo.to_string(),
+            candidates: self.file_paths(repo, name).to_vec(),
+        })
+    }
+
+    pub(crate) fn find_commit_in_clone(&self, repo: &str, name: &str, version: &str) -> Result<(String, String)> {
+        info!("Looking for {}@{} in the local clone of {}", name, version, repo);
+
+        let tap = self.local_history(repo)?;
+        for file_path in self.file_paths(repo, name) {
+            let commits = tap.commits(&file_path)?;
+            let count = commits.len();
+            let found = commits.into_iter()
+                .find(|commit| self.matches_commit(repo, name, version, &commit.subject, || tap.show(&commit.sha, &file_path)));
+            self.note(format!("Probed {} in the local clone: {} commits, {}", file_path, count, if found.is_some() { "one of them matched" } else { "none matched" }));
+            if let Some(commit) = found {
+                self.log_revision(name, version, &commit.subject);
+                self.note_match(repo, name, version, &commit.sha, &file_path, &commit.subject);
+                info!(commit = commit.sha.as_str(); "Found Commit: {}", commit.sha);
+                return Ok((commit.sha, file_path));
+            }
+        }
+        Err(BrewverError::CommitNotFound {
+            name: name.to_string(),
+            version: version.to_string(),
+            repo: repo.to_string(),
+            candidates: self.file_paths(repo, name).to_vec(),
+        })
+    }
+
+    fn find_commit_by_content(&self, repo: &str, name: &str, version: &str) -> Result<(String, String)> {
+        info!("Looking for the formula file of {}@{} in {}", name, version, repo);
+
+        let mut commits: Vec<(String, String)> = Vec::new();
+        self.walk_file_histories(repo, name, self.backend == Backend::Graphql, |file_path, commit| {
+            let sha = commit.get("sha").and_then(|s| s.as_str()).unwrap_or_default();
+            commits.push((file_path.to_string(), sha.to_string()));
+            false
+        })?;
+        self.search_contents(repo, name, version, &commits, |file_path, sha| {
+            let url = format_gh_api_raw_file_url(self.client.raw_url(), repo, sha, file_path);
+            self.client.download(&url).ok().filter(|response| response.is_success()).map(|response| response.body)
+        })
+    }
+
+    fn find_commit_by_content_in_clone(&self, repo: &str, name: &str, version: &str) -> Result<(String, String)> {
+        info!("Looking for the formula file of {}@{} in the local clone of {}", name, version, repo);
+
+        let tap = self.local_history(repo)?;
+        let mut commits: Vec<(String, String)> = Vec::new();
+        for file_path in self.file_paths(repo, name) {
+            commits.extend(tap.commits(&file_path)?.into_iter().map(|commit| (file_path.clone(), commit.sha)));
+        }
+        self.search_contents(repo, name, version, &commits, |file_path, sha| tap.show(sha, file_path))
+    }
+
+    /// Binary-searches the commits of the file, newest first, for the newest one whose file
+    /// declares the version, assuming versions only went up over the history. Commits whose file
+    /// declares no version are skipped over towards older ones.
+    fn search_contents(
+        &self,
+        repo: &str,
+        name: &str,
+        version: &str,
+        commits: &[(String, String)],
+        contents: impl Fn(&str, &str) -> Option<String>,
+    ) -> Result<(String, String)> {
+        let wanted = version.strip_suffix(&format!("_{}", self.revision.unwrap_or_default())).unwrap_or(version);
+        let mut declared: Vec<Option<Option<String>>> = vec![None; commits.len()];
+        // The version declared at the commit, or at the next older one that declares any.
+        let mut version_at = |mut index: usize| -> Option<(usize, String)> {
+            while index < commits.len() {
+                let (file_path, sha) = &commits[index];
+                let found = declared[index].get_or_insert_with(|| {
+                    let found = contents(file_path, sha).and_then(|contents| self.file_version(&contents));
+                    debug!("{} declares version {:?} at {}", name, found, sha);
+                    found
+                });
+                if let Some(found) = found {
+                    return Some((index, found.clone()));
+                }
+                index += 1;
+            }
+            None
+        };
+
+        // The first index whose version is at most the wanted one.
+        let (mut low, mut high) = (0, commits.len());
+        while low < high {
+            let middle = low + (high - low) / 2;
+            match version_at(middle) {
+                Some((_, found)) if version_key(&found) > version_key(wanted) => low = middle + 1,
+                _ => high = middle,
+            }
+        }
+        let found = version_at(low);
+        let read = declared.iter().filter(|declared| declared.is_some()).count();
+        self.note(format!("Binary-searched the formula files of {} commits, reading {} of them", commits.len(), read));
+        match found {
+            Some((index, found)) if is_revision_of(version, &found) => {
+                let (file_path, sha) = &commits[index];
+                self.note(format!("Matched commit {} at {}: it is the newest whose formula file declares version {}", sha, file_path, found));
+                info!(commit = sha.as_str(); "Found Commit: {}", sha);
+                Ok((sha.clone(), file_path.clone()))
+            }
+            _ => Err(BrewverError::CommitNotFound {
+                name: name.to_string(),
+                version: version.to_string(),
+                repo: repo.to_string(),
+                candidates: self.file_paths(repo, name).to_vec(),
+            }),
+        }
+    }
+
+    fn find_commit_until(&self, repo: &str, name: &str, until: &str) -> Result<(String, String)> {
+        info!("Looking for {} as of {} in {}", name, until, repo);
+
+        for file_path in self.file_paths(repo, name) {
+            let url = format_gh_api_commits_until_url(self.client.api_url(), repo, &file_path, until);
+            debug!("URL: {:?}", &url);
+
+            let commits = self.client.get(&url)?.api_json()?;
+            if let Some(sha) = commits.pointer("/0/sha").and_then(|s| s.as_str()) {
+                info!(commit = sha; "Found Commit: {}", sha);
+                return Ok((sha.to_string(), file_path));
+            }
+        }
+        Err(BrewverError::FileNotFound {
+            name: name.to_string(),
+            git_ref: until.to_string(),
+            repo: repo.to_string(),
+            candidates: self.file_paths(repo, name).to_vec(),
+        })
+    }
+
+    fn find_commit_until_in_clone(&self, repo: &str, name: &str, until: &str) -> Result<(String, String)> {
+        info!("Looking for {} as of {} in the local clone of {}", name, until, repo);
+
+        let tap = self.local_history(repo)?;
+        for file_path in self.file_paths(repo, name) {
+            if let Some(commit) = tap.last_commit_until(&file_path, until)?.filter(|commit| tap.has_file(&commit.sha, &file_path)) {
+                info!(commit = commit.sha.as_str(); "Found Commit: {}", commit.sha);
+                return Ok((commit.sha, file_path));
+            }
+        }
+        Err(BrewverError::FileNotFound {
+            name: name.to_string(),
+            git_ref: until.to_string(),
+            repo: repo.to_string(),
+            candidates: self.file_paths(repo, name).to_vec(),
+        })
+    }
+
+    /// Takes the commit and path from the Homebrew API, which reports the tap commit its data was
+    /// generated from. Only the current version can be found this way.
+    pub(crate) fn find_commit_in_brew_api(&self, name: &str, version: &str) -> Result<(String, String)> {
+        info!("Looking for {}@{} in the Homebrew API", name, version);
+
+        let url = format_brew_api_url(name, self.cask);
+        debug!("URL: {:?}", &url);
+        let response = self.client.get(&url)?;
+        if response.status == 404 {
+            return Err(BrewverError::NotKnownToBrew { name: name.to_string() });
+        }
+        if !response.is_success() {
+            return Err(BrewverError::HttpStatus { url: response.url, status: response.status });
+        }
+
+        let info = response.json()?;
+        let field = |pointer: &str| info.pointer(pointer).and_then(|v| v.as_str()).map(String::from);
+        let current = if self.cask {
+            field("/version")
+        } else {
+            let revision = info.get("revision").and_then(|r| r.as_u64()).unwrap_or_default();
+            field("/versions/stable").map(|stable| if revision > 0 { format!("{}_{}", stable, revision) } else { stable })
+        };
+        let (Some(current), Some(commit), Some(path)) = (current, field("/tap_git_head"), field("/ruby_source_path")) else {
+            return Err(BrewverError::UnexpectedResponse { url, reason: "no version, tap_git_head or ruby_source_path".to_string() });
+        };
+
+        if current != version {
+            return Err(BrewverError::NotInBrewApi { name: name.to_string(), version: version.to_string(), current });
+        }
+        self.note(format!("Took commit {} from the Homebrew API, which only knows the current version", commit));
+        info!(commit = commit.as_str(); "Found Commit: {}", commit);
+        Ok((commit, format!("/{}", path)))
+    }
+
+    fn find_versions(&self, repo: &str, name: &str) -> Result<Vec<FormulaVersion>> {
+        info!("Looking for versions of {} in {}", name, repo);
+        self.note(format!("Listing the versions in the history of {}", name));
+
+        let mut versions: Vec<FormulaVersion> = Vec::new();
+        self.walk_file_histories(repo, name, self.backend == Backend::Graphql, |_, commit| {
+            let version = commit.pointer("/commit/message")
+                .and_then(|m| m.as_str())
+                .and_then(|m| self.commit_version(repo, name, m));
+            if let Some(version) = version.filter(|version| !versions.iter().any(|v| v.version == *version)) {
+                versions.push(FormulaVersion {
+                    version,
+                    commit: commit.get("sha").and_then(|s| s.as_str()).unwrap_or_default().to_string(),
+                    date: commit.pointer("/commit/committer/date").and_then(|d| d.as_str()).unwrap_or_default().to_string(),
+                });
+            }
+            false
+        })?;
+        Ok(versions)
+    }
+
+    fn find_versions_in_clone(&self, repo: &str, name: &str) -> Result<Vec<FormulaVersion>> {
+        info!("Looking for versions of {} in the local clone of {}", name, repo);
+        self.note(format!("Listing the versions in the history of {} in the local clone", name));
+
+        let tap = self.local_history(repo)?;
+        let mut versions: Vec<FormulaVersion> = Vec::new();
+        for file_path in self.file_paths(repo, name) {
+            for commit in tap.commits(&file_path)? {
+                let Some(version) = self.commit_version(repo, name, &commit.subject) else {
+                    continue;
+                };
+                if !versions.iter().any(|v| v.version == version) {
+                    versions.push(FormulaVersion { version, commit: commit.sha, date: commit.date });
+                }
+            }
+        }
+        Ok(versions)
+    }
+
+    /// The repository of the given tap, the configured one or the default for formulas or casks.
+    fn repo(&self, tap: Option<&str>) -> String {
+        match tap.or(self.tap.as_deref()) {
+            Some(tap) => tap_repo(tap),
+            None if self.cask => CASK_REPO.to_string(),
+            None => CORE_REPO.to_string(),
+        }
+    }
+
+    /// The paths the file may be at. Besides the sharded and flat `Formula` directories of
+    /// homebrew-core, taps may keep formulas in `HomebrewFormula` or at the repository root.
+    fn file_paths(&self, repo: &str, name: &str) -> Vec<String> {
+        if self.cask {
+            return get_file_path("Casks", name).to_vec();
+        }
+        let mut paths = get_file_path("Formula", name).to_vec();
+        if is_tap(repo) {
+            paths.push(format!("/HomebrewFormula/{}.rb", name));
+            paths.push(format!("/{}.rb", name));
+        }
+        paths
+    }
+
+    /// Whether a commit message marks the commit that bottled (or, for casks, bumped) the version.
+    /// Taps rarely have bottle commits, so their version bumps are accepted as well. Unless a
+    /// revision was requested, bottles of later revisions of the version count too; history is
+    /// walked newest first, so the first of them found is the newest revision.
+    fn is_version_commit(&self, repo: &str, name: &str, version: &str, message: &str) -> bool {
+        if self.cask {
+            cask_version(name, message).as_deref() == Some(version)
+        } else {
+            self.is_bottle_commit(name, version, message)
+                || (is_tap(repo) && cask_version(name, message).as_deref() == Some(version))
+                || (self.match_strategy == MatchStrategy::Bump && bump_version(name, message).is_some_and(|found| self.is_found_version(&found, version)))
+        }
+    }
+
+    fn is_bottle_commit(&self, name: &str, version: &str, message: &str) -> bool {
+        message.contains(&commit_message(name, version))
+            || bottle_version(name, message).is_some_and(|found| self.is_found_version(&found, version))
+    }
+
+    /// Whether the version of a commit is the requested one or, unless a revision was requested,
+    /// a revision of it.
+    fn is_found_version(&self, found: &str, version: &str) -> bool {
+        found == version || (self.revision.is_none() && is_revision_of(found, version))
+    }
+
+    /// Whether the commit is the one of the version under the match strategy. With
+    /// [`MatchStrategy::Verified`], the formula file at any commit of the formula other than a
+    /// bottle commit is read with `contents` and has to declare the version.
+    fn matches_commit(&self, repo: &str, name: &str, version: &str, message: &str, contents: impl FnOnce() -> Option<String>) -> bool {
+        if self.cask || self.match_strategy != MatchStrategy::Verified {
+            return self.is_version_commit(repo, name, version, message);
+        }
+        if self.is_bottle_commit(name, version, message) {
+            return true;
+        }
+        if !mentions_formula(name, message) {
+            return false;
+        }
+        let declared = contents().and_then(|contents| self.file_version(&contents));
+        debug!("{} declares version {:?} at {:?}", name, declared, message.lines().next().unwrap_or_default());
+        declared.is_some_and(|declared| is_revision_of(version, &declared))
+    }
+
+    fn log_revision(&self, name: &str, version: &str, message: &str) {
+        let found = bottle_version(name, message)
+            .or_else(|| if self.match_strategy == MatchStrategy::Bottle { None } else { bump_version(name, message) });
+        if let Some(found) = found.filter(|found| found != version) {
+            info!("Using {} {}, the newest revision of {}", name, found, version);
+        }
+    }
+
+    fn commit_version(&self, repo: &str, name: &str, message: &str) -> Option<String> {
+        if self.cask {
+            cask_version(name, message)
+        } else {
+            bottle_version(name, message)
+                .or_else(|| if is_tap(repo) { cask_version(name, message) } else { None })
+                .or_else(|| if self.match_strategy == MatchStrategy::Bottle { None } else { bump_version(name, message) })
+        }
+    }
+
+    /// Walks the commit histories of the paths the file may be at like [`Self::walk_commits`], then,
+    /// unless `visit` accepted a commit, the histories of the paths the file was moved from, such
+    /// as a flat `Formula` path before homebrew-core was sharded, or the path of a formula before
+    /// it was renamed.
+    fn walk_file_histories(&self, repo: &str, name: &str, graphql: bool, mut visit: impl FnMut(&str, &serde_json::Value) -> bool) -> Result<Option<(String, serde_json::Value)>> {
+        let mut file_paths = self.file_paths(repo, name);
+        let mut walked: Vec<String> = Vec::new();
+        for _ in 0..=MAX_RENAMES {
+            // The oldest commit of each history, the one that may have moved the file there.
+            let mut oldest: Vec<(String, String)> = Vec::new();
+            let found = self.walk_commits(repo, &file_paths, graphql, |file_path, commit| {
+                let sha = commit.get("sha").and_then(|s| s.as_str()).unwrap_or_default().to_string();
+                match oldest.last_mut() {
+                    Some((path, oldest)) if path == file_path => *oldest = sha,
+                    _ => oldest.push((file_path.to_string(), sha)),
+                }
+                visit(file_path, commit)
+            })?;
+            if found.is_some() {
+                return Ok(found);
+            }
+            walked.extend(file_paths);
+            file_paths = Vec::new();
+            for (file_path, sha) in oldest {
+                if let Some(previous) = self.previous_path(repo, &file_path, &sha)?.filter(|path| !walked.contains(path) && !file_paths.contains(path)) {
+                    info!("{} was moved from {} in {}, looking there too", file_path, previous, sha);
+                    self.note(format!("Following {} back to {}, where commit {} moved it from", file_path, previous, sha));
+                    file_paths.push(previous);
+                }
+            }
+            if file_paths.is_empty() {
+                break;
+            }
+        }
+        Ok(None)
+    }
+
+    /// The path the file was at before the commit, if the commit renamed it. Lookup failures other
+    /// than rate limits count as no rename, since the file may simply have been added there.
+    fn previous_path(&self, repo: &str, file_path: &str, sha: &str) -> Result<Option<String>> {
+        let url = format_gh_api_commit_url(self.client.api_url(), repo, sha);
+        debug!("URL: {:?}", &url);
+        let commit = match self.client.get(&url).and_then(|response| response.api_json()) {
+            Ok(commit) => commit,
+            Err(e @ (BrewverError::RateLimited { .. } | BrewverError::RateLimitInsufficient { .. })) => return Err(e),
+            Err(e) => {
+                debug!("Failed to look up the files of {}: {}", sha, e);
+                return Ok(None);
+            }
+        };
+        Ok(commit.get("files").and_then(|files| files.as_array()).into_iter().flatten()
+            .find(|file| {
+                file.get("filename").and_then(|f| f.as_str()) == Some(file_path.trim_start_matches('/'))
+                    && file.get("status").and_then(|s| s.as_str()) == Some("renamed")
+            })
+            .and_then(|file| file.get("previous_filename")?.as_str())
+            .map(|previous| format!("/{}", previous)))
+    }
+
+    /// Walks the commit histories of the paths one after another, each newest first, until `visit`
+    /// accepts a commit or `max_pages` pages of a path were read. Returns the accepted commit and
+    /// its path.
+    ///
+    /// The first pages of all paths are requested at once. Once the Link header of a first page
+    /// tells how many pages its history has, the following pages are requested `PAGE_BATCH` at a
+    /// time; commits are still visited in order, so the result is the same as walking page by page.
+    fn walk_commits(&self, repo: &str, file_paths: &[String], graphql: bool, mut visit: impl FnMut(&str, &serde_json::Value) -> bool) -> Result<Option<(String, serde_json::Value)>> {
+        if self.max_pages == 0 {
+            return Ok(None);
+        }
+        if graphql {
+            if self.client.has_token() {
+                return self.walk_commits_graphql(repo, file_paths, visit);
+            }
+            warn!("The GitHub GraphQL API needs a token, using the REST API instead");
+        }
+        let urls: Vec<String> = file_paths.iter()
+            .map(|file_path| format_gh_api_commits_url(self.client.api_url(), repo, file_path))
+            .collect();
+        debug!("URLs: {:?}", &urls);
+
+        for (file_path, response) in file_paths.iter().zip(self.get_history_pages(&urls)) {
+            let response = response?;
+            let mut pages = 1;
+            if let Some(commit) = visit_page(&response, &mut |commit| visit(file_path, commit))? {
+                self.note_probe(file_path, pages, true);
+                return Ok(Some((file_path.clone(), commit)));
+            }
+            let Some(next_url) = next_page_url(&response) else {
+                self.note_probe(file_path, pages, false);
+                continue;
+            };
+            let last_page = last_page_url(&response).and_then(|url| page_number(&url));
+
+            let mut page = page_number(&next_url).unwrap_or(2);
+            check_remaining_requests(&response, file_path, last_page.map(|last| last.min(self.max_pages).saturating_sub(page) + 1))?;
+            loop {
+                if page > self.max_pages {
+                    warn!("Stopped after {} pages of {} history, use --max-pages to search further", self.max_pages, file_path);
+                    self.note(format!("Stopped at --max-pages {} in the history of {}", self.max_pages, file_path));
+                    break;
+                }
+                let end = last_page.unwrap_or(page).min(self.max_pages).min(page + PAGE_BATCH - 1).max(page);
+                let urls: Vec<String> = (page..=end).map(|page| with_page_number(&next_url, page)).collect();
+                debug!("URLs: {:?}", &urls);
+
+                let mut has_next = false;
+                for response in self.get_history_pages(&urls) {
+                    let response = response?;
+                    pages += 1;
+                    if let Some(commit) = visit_page(&response, &mut |commit| visit(file_path, commit))? {
+                        self.note_probe(file_path, pages, true);
+                        return Ok(Some((file_path.clone(), commit)));
+                    }
+                    has_next = next_page_url(&response).is_some();
+                }
+                if !has_next {
+                    break;
+                }
+                page = end + 1;
+            }
+            self.note_probe(file_path, pages, false);
+        }
+        Ok(None)
+    }
+
+    fn note_probe(&self, file_path: &str, pages: u32, found: bool) {
+        let pages = if pages == 1 { "1 page".to_string() } else { format!("{} pages", pages) };
+        self.note(format!("Probed {}: read {} of its history, {}", file_path, pages, if found { "stopping at the commit looked for" } else { "without finding the commit looked for" }));
+    }
+
+    /// Notes the commit that matched, and which rule of the match strategy it matched by.
+    fn note_match(&self, repo: &str, name: &str, version: &str, sha: &str, file_path: &str, message: &str) {
+        let reason = if self.cask || (is_tap(repo) && cask_version(name, message).as_deref() == Some(version)) {
+            "its message bumps the version"
+        } else if self.is_bottle_commit(name, version, message) {
+            "its message is the bottle commit of the version"
+        } else if self.match_strategy == MatchStrategy::Bump {
+            "its message bumps the formula to the version (--match-strategy bump)"
+        } else {
+            "its formula file declares the version (--match-strategy verified)"
+        };
+        self.note(format!("Matched commit {} at {}: \"{}\", {}", sha, file_path, message.lines().next().unwrap_or_default(), reason));
+    }
+
+    /// Requests the pages of commit histories that weren't read before, answering the others from
+    /// memory.
+    fn get_history_pages(&self, urls: &[String]) -> Vec<Result<Response>> {
+        let missing: Vec<String> = urls.iter().filter(|url| !self.history_pages.borrow().contains_key(*url)).cloned().collect();
+        let mut fetched: HashMap<String, Result<Response>> = missing.iter().cloned().zip(self.client.get_all(&missing)).collect();
+        urls.iter().map(|url| {
+            if let Some(response) = self.history_pages.borrow().get(url) {
+                debug!("Reusing {}", url);
+                return Ok(response.clone());
+            }
+            let response = fetched.remove(url).unwrap_or_else(|| self.client.get(url))?;
+            if response.is_success() {
+                self.history_pages.borrow_mut().insert(url.clone(), response.clone());
+            }
+            Ok(response)
+        }).collect()
+    }
+
+    /// Walks the commit histories like [`Self::walk_commits`] through the GraphQL API. One query
+    /// reads the first pages of all paths, then each further page of a path takes one query.
+    fn walk_commits_graphql(&self, repo: &str, file_paths: &[String], mut visit: impl FnMut(&str, &serde_json::Value) -> bool) -> Result<Option<(String, serde_json::Value)>> {
+        let first_pages: Vec<(&str, Option<&str>)> = file_paths.iter().map(|path| (path.as_str(), None)).collect();
+        for (file_path, mut history) in file_paths.iter().zip(self.query_histories(repo, &first_pages)?) {
+            let mut pages = 1;
+            loop {
+                if let Some(commit) = history.commits.into_iter().find(|commit| visit(file_path, commit)) {
+                    self.note_probe(file_path, pages, true);
+                    return Ok(Some((file_path.clone(), commit)));
+                }
+                let Some(cursor) = history.end_cursor else {
+                    break;
+                };
+                if pages == self.max_pages {
+                    warn!("Stopped after {} pages of {} history, use --max-pages to search further", pages, file_path);
+                    self.note(format!("Stopped at --max-pages {} in the history of {}", self.max_pages, file_path));
+                    break;
+                }
+                pages += 1;
+                history = self.query_histories(repo, &[(file_path, Some(&cursor))])?.remove(0);
+            }
+            self.note_probe(file_path, pages, false);
+        }
+        Ok(None)
+    }
+
+    /// Runs a history query, returning a page of commits for each path, shaped like the commits
+    /// of the REST API.
+    fn query_histories(&self, repo: &str, paths: &[(&str, Option<&str>)]) -> Result<Vec<History>> {
+        let url = format_gh_graphql_url(self.client.api_url());
+        let query = format_gh_graphql_history_query(repo, paths);
+        debug!("GraphQL query: {}", query);
+
+        let cached = self.history_pages.borrow().get(&query).cloned();
+        let response = match cached {
+            Some(response) => response,
+            None => self.client.post(&url, &serde_json::json!({ "query": query }))?,
+        };
+        let unexpected = |reason: String| BrewverError::UnexpectedResponse { url: url.clone(), reason };
+        let json = response.api_json()?;
+        if let Some(errors) = json.get("errors").and_then(|e| e.as_array()) {
+            if errors.iter().any(|e| e.get("type").and_then(|t| t.as_str()) == Some("RATE_LIMITED")) {
+                let reset_at = response.header("x-ratelimit-reset").and_then(|v| v.parse().ok()).unwrap_or_default();
+                let message = format!("GitHub GraphQL API rate limit exceeded, it resets at {}", reset_at);
+                return Err(BrewverError::RateLimited { reset_at, message });
+            }
+            let messages: Vec<&str> = errors.iter().filter_map(|e| e.get("message")?.as_str()).collect();
+            return Err(unexpected(messages.join("; ")));
+        }
+
+        let histories = (0..paths.len()).map(|i| {
+            let history = json.pointer(&format!("/data/repository/defaultBranchRef/target/h{}", i))
+                .ok_or_else(|| unexpected(format!("no history h{}", i)))?;
+            let has_next = history.pointer("/pageInfo/hasNextPage").and_then(|v| v.as_bool()).unwrap_or(false);
+            let commits = history.get("nodes").and_then(|n| n.as_array()).into_iter().flatten().map(|node| {
+                serde_json::json!({
+                    "sha": node.get("oid"),
+                    "commit": { "message": node.get("message"), "committer": { "date": node.get("committedDate") } },
+                })
+            }).collect();
+            Ok(History {
+                commits,
+                end_cursor: history.pointer("/pageInfo/endCursor").and_then(|c| c.as_str()).filter(|_| has_next).map(String::from),
+            })
+        }).collect::<Result<Vec<History>>>()?;
+        self.history_pages.borrow_mut().insert(query, response);
+        Ok(histories)
+    }
+
+    fn find_tagged_file(&self, repo: &str, name: &str, tag: &str) -> Result<String> {
+        info!("Looking for {} at {} in {}", name, tag, repo);
+
+        for file_path in self.file_paths(repo, name) {
+            let url = format_gh_api_contents_url(self.client.api_url(), repo, &file_path, tag);
+            debug!("URL: {:?}", &url);
+
+            if self.client.get(&url)?.is_success() {
+                info!("Found {} at {}", file_path, tag);
+                return Ok(file_path);
+            }
+        }
+        Err(BrewverError::FileNotFound {
+            name: name.to_string(),
+            git_ref: tag.to_string(),
+            repo: repo.to_string(),
+            candidates: self.file_paths(repo, name).to_vec(),
+        })
+    }
+
+    fn find_tagged_file_in_clone(&self, repo: &str, name: &str, tag: &str) -> Result<String> {
+        info!("Looking for {} at {} in the local clone of {}", name, tag, repo);
+
+        let tap = self.local_history(repo)?;
+        for file_path in self.file_paths(repo, name) {
+            if tap.has_file(tag, &file_path) {
+                info!("Found {} at {}", file_path, tag);
+                return Ok(file_path);
+            }
+        }
+        Err(BrewverError::FileNotFound {
+            name: name.to_string(),
+            git_ref: tag.to_string(),
+            repo: repo.to_string(),
+            candidates: self.file_paths(repo, name).to_vec(),
+        })
+    }
+
+    fn verify_commit(&self, formula: &Formula) -> Result<()> {
+        let url = format_gh_api_repo_url(self.client.api_url(), &formula.repo);
+        let repo: serde_json::Value = self.client.get(&url)?.api_json()?;
+        let branch = repo.get("default_branch")
+            .and_then(|b| b.as_str())
+            .ok_or_else(|| BrewverError::UnexpectedResponse { url: url.clone(), reason: "no default branch".to_string() })?;
+
+        let comparison: serde_json::Value = self.client.get(&format_gh_api_compare_url(self.client.api_url(), &formula.repo, &formula.commit, branch))?.api_json()?;
+        match comparison.get("status").and_then(|s| s.as_str()) {
+            Some("ahead") | Some("identical") => debug!("Commit {} is on {}", formula.commit, branch),
+            status => warn!("Commit {} is not on the default branch {} (compare status: {})", formula.commit, branch, status.unwrap_or("unknown")),
+        }
+        Ok(())
+    }
+}
+
+/// A page of the history of a path from the GraphQL API, and the cursor of the next page if there is one.
+struct History {
+    commits: Vec<serde_json::Value>,
+    end_cursor: Option<String>,
+}
+
+/// Fails before a deep search when the rate limit reported with the first page can't cover the
+/// remaining pages, instead of running out of requests halfway through.
+fn check_remaining_requests(response: &Response, file_path: &str, needed: Option<u32>) -> Result<()> {
+    let (Some((remaining, reset_at)), Some(needed)) = (rate_limit_remaining(response), needed) else {
+        return Ok(());
+    };
+    debug!("GitHub API requests remaining: {}, the rest of {} takes up to {}", remaining, file_path, needed);
+    if remaining >= u64::from(needed) {
+        return Ok(());
+    }
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    Err(BrewverError::RateLimitInsufficient {
+        path: file_path.to_string(),
+        remaining,
+        needed: u64::from(needed),
+        reset_in: reset_at.saturating_sub(now),
+    })
+}
+
+/// Returns the first commit of a page of a commit history that `visit` accepts.
+fn visit_page(response: &Response, visit: &mut impl FnMut(&serde_json::Value) -> bool) -> Result<Option<serde_json::Value>> {
+    match response.api_json()? {
+        serde_json::Value::Array(commits) => Ok(commits.into_iter().find(|commit| visit(commit))),
+        _ => Err(BrewverError::UnexpectedResponse { url: response.url.clone(), reason: "not a list of commits".to_string() }),
+    }
+}
+
+fn commit_message(name: &str, version: &str) -> String {
+    format!("{}: update {} bottle", name, version)
+}
+
+/// Extracts the version from a `<name>: update <version> bottle` commit message.
+fn bottle_version(name: &str, message: &str) -> Option<String> {
+    message.strip_prefix(name)?
+        .strip_prefix(": update ")?
+        .split_once(" bottle")
+        .map(|(version, _)| version.to_string())
+}
+
+/// Extracts the version from a version bump commit message such as `<name> <version>`,
+/// `<name>: update to <version>`, `<name>: bump <old> -> <version>` or
+/// `<name>: update from <old> to <version>`, including bottle commit messages.
+fn bump_version(name: &str, message: &str) -> Option<String> {
+    let subject = message.lines().next()?.strip_prefix(name)?;
+    let rest = subject.strip_prefix(':').or_else(|| subject.strip_prefix(' '))?;
+    let mut words = rest.split_whitespace()
+        .skip_while(|word| matches!(word.to_ascii_lowercase().as_str(), "update" | "updated" | "upgrade" | "bump" | "version" | "to"))
+        .peekable();
+    if words.next_if_eq(&"from").is_some() {
+        words.next();
+    }
+    let mut version = words.next()?;
+    if let Some(new) = words.next_if(|word| *word == "->" || *word == "to").and_then(|_| words.next()) {
+        version = new;
+    }
+    Some(version.trim_end_matches(['.', ',', ':', ';']))
+        .filter(|version| version.starts_with(|c: char| c.is_ascii_digit()))
+        .map(String::from)
+}
+
+/// Whether the subject of a commit message starts with the formula name, as commits to a
+/// formula in homebrew-core do.
+fn mentions_formula(name: &str, message: &str) -> bool {
+    message.strip_prefix(name).is_some_and(|rest| rest.starts_with([':', ' ']))
+}
+
+/// The highest version that equals the requested one or extends it by further components or a
+/// revision, so `1.2` matches `1.2.3` and `1.2.4_1` but not `1.20`.
+fn nearest_version<'v>(requested: &str, versions: &'v [FormulaVersion]) -> Option<&'v FormulaVersion> {
+    versions.iter()
+        .filter(|v| {
+            v.version == requested
+                || v.version.strip_prefix(requested).is_some_and(|rest| rest.starts_with(['.', '_', '-']))
+        })
+        .max_by(|a, b| version_key(&a.version).cmp(&version_key(&b.version)))
+}
+
+/// Whether a version stands for the versions matching it rather than for itself: a glob such
+/// as `14.*` or `1.2.?`, or a range of comma-separated comparators such as `>=14.2, <16`, `^14.2`
+/// (below `15`) or `~14.2` (below `14.3`).
+pub fn is_version_pattern(version: &str) -> bool {
+    version.contains(['*', '?']) || version.starts_with(['^', '~', '<', '>', '='])
+}
+
+/// Whether the version matches a pattern from [`is_version_pattern`].
+fn matches_pattern(pattern: &str, version: &str) -> bool {
+    if pattern.contains(['*', '?']) {
+        return matches_glob(pattern.as_bytes(), version.as_bytes());
+    }
+    pattern.split(',').map(str::trim).filter(|comparator| !comparator.is_empty()).all(|comparator| {
+        let (op, bound) = comparator.split_at(comparator.find(|c: char| c.is_ascii_alphanumeric()).unwrap_or(comparator.len()));
+        let (found, bound) = (version_key(version), version_key(bound.trim()));
+        match op.trim() {
+            ">=" => found >= bound,
+            ">" => found > bound,
+            "<=" => found <= bound,
+            "<" => found < bound,
+            "=" => found == bound,
+            "^" => {
+                // Bumps the first component that isn't zero, so `^0.4` stays below `0.5`.
+                let bumped = bound.iter().position(|(number, _)| *number > 0).unwrap_or(0);
+                found >= bound && found < upper_bound(&bound, bumped)
+            }
+            "~" => found >= bound && found < upper_bound(&bound, bound.len().min(2).saturating_sub(1)),
+            _ => false,
+        }
+    })
+}
+
+/// The version after `bound` with the component at `index` one higher and the ones after it
+/// dropped, such as `15` for `14.2` at 0.
+fn upper_bound(bound: &[(u64, String)], index: usize) -> Vec<(u64, String)> {
+    let mut upper = bound[..(index + 1).min(bound.len())].to_vec();
+    if let Some((number, _)) = upper.last_mut() {
+        *number += 1;
+    }
+    upper
+}
+
+/// Matches text against a glob where `*` stands for any run of characters and `?` for one.
+fn matches_glob(pattern: &[u8], text: &[u8]) -> bool {
+    match (pattern.split_first(), text.split_first()) {
+        (Some((b'*', rest)), _) => matches_glob(rest, text) || text.split_first().is_some_and(|(_, text)| matches_glob(pattern, text)),
+        (Some((b'?', rest)), Some((_, text))) => matches_glob(rest, text),
+        (Some((expected, rest)), Some((found, text))) => expected == found && matches_glob(rest, text),
+        (Some(_), None) => false,
+        (None, text) => text.is_none(),
+    }
+}
+
+/// Splits a version into components that compare numerically where they are numbers.
+pub(crate) fn version_key(version: &str) -> Vec<(u64, String)> {
+    version.split(['.', '_', '-'])
+        .map(|part| match part.parse() {
+            Ok(number) => (number, String::new()),
+            Err(_) => (0, part.to_string()),
+        })
+        .collect()
+}
+
+/// Whether `found` is `version` itself or a revision of it such as `version_1`.
+fn is_revision_of(found: &str, version: &str) -> bool {
+    found == version
+        || found.strip_prefix(version)
+            .and_then(|rest| rest.strip_prefix('_'))
+            .is_some_and(|revision| revision.parse::<u32>().is_ok())
+}
+
+pub(crate) fn is_tap(repo: &str) -> bool {
+    repo != CORE_REPO && repo != CASK_REPO
+}
+
+/// The name brew knows a formula by, without its tap, lowercased unless `normalize_case` is off.
+pub fn brew_name(name: &str, normalize_case: bool) -> String {
+    let name = if normalize_case { name.to_lowercase() } else { name.to_string() };
+    split_tap(&name).1.to_string()
+}
+
+/// Splits a fully qualified `user/repo/name` into its tap and name.
+fn split_tap(name: &str) -> (Option<&str>, &str) {
+    match name.rsplit_once('/') {
+        Some((tap, name)) if tap.contains('/') => (Some(tap), name),
+        _ => (None, name),
+    }
+}
+
+/// Extracts the version from a `<token> <version>` or `Update <token> from <old> to <version>`
+/// commit message, as used by homebrew-cask and many taps.
+fn cask_version(token: &str, message: &str) -> Option<String> {
+    let subject = message.lines().next()?;
+    let version = match subject.strip_prefix(token).and_then(|rest| rest.strip_prefix(' ')) {
+        Some(rest) => rest,
+        None => subject.strip_prefix("Update ")?.strip_prefix(token)?.strip_prefix(" from ")?.split_once(" to ")?.1,
+    };
+    version.split_whitespace()
+        .next()
+        .filter(|version| version.starts_with(|c: char| c.is_ascii_digit()))
+        .map(String::from)
+}
+
+fn normalize_name(name: &str) -> String {
+    let normalized = name.to_lowercase();
+    if normalized != name {
+        warn!("Formula name {} was normalized to {}", name, normalized);
+    }
+    normalized
+}
+
+/// Fails for a name without a formula in it, such as `""` or `user/tap/`, from which no file path
+/// can be made.
+fn check_name(name: &str, brew_name: &str) -> Result<()> {
+    if brew_name.trim().is_empty() {
+        return Err(BrewverError::EmptyName(name.to_string()));
+    }
+    Ok(())
+}
+
+fn get_file_path(dir: &str, name: &str) -> [String; 2] {
+    let first_letter: String = name.chars().take(1).collect();
+    [
+        format!("/{}/{}/{}.rb", dir, first_letter, name),
+        format!("/{}/{}.rb", dir, name),
+    ]
+}
+
+/// The Levenshtein distance between two names, in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(a != *b);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
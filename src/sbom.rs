@@ -0,0 +1,104 @@
+//! Software bills of materials of the formulas brewver installed, for `brewver sbom`, as
+//! CycloneDX 1.5 or SPDX 2.3 JSON documents.
+
+use serde_json::{json, Value};
+
+/// A formula version brewver installed, with what its formula file declares.
+pub(crate) struct Component {
+    pub(crate) name: String,
+    pub(crate) version: String,
+    /// The repository and commit the formula file was taken from, empty for local files.
+    pub(crate) repo: String,
+    pub(crate) commit: String,
+    /// The URL of the source archive.
+    pub(crate) source_url: Option<String>,
+    pub(crate) homepage: Option<String>,
+    /// The SPDX license expression, when the formula file declares a single license.
+    pub(crate) license: Option<String>,
+}
+
+impl Component {
+    fn commit_url(&self) -> Option<String> {
+        (!self.repo.is_empty() && !self.commit.is_empty()).then(|| format!("https://github.com/{}/commit/{}", self.repo, self.commit))
+    }
+}
+
+/// The license of a formula file as an SPDX identifier, unless it is a symbol like
+/// `:public_domain` or a choice like `any_of: [...]`.
+pub(crate) fn spdx_license(license: &str) -> Option<String> {
+    Some(license.to_string())
+        .filter(|license| !license.is_empty() && license.chars().all(|c| c.is_ascii_alphanumeric() || ".+-".contains(c)))
+}
+
+pub(crate) fn cyclonedx(components: &[Component], timestamp: &str) -> Value {
+    let components: Vec<Value> = components.iter().map(|component| {
+        let mut references = Vec::new();
+        if let Some(url) = &component.source_url {
+            references.push(json!({ "type": "distribution", "url": url }));
+        }
+        if let Some(url) = &component.homepage {
+            references.push(json!({ "type": "website", "url": url }));
+        }
+        if let Some(url) = component.commit_url() {
+            references.push(json!({ "type": "vcs", "url": url }));
+        }
+        let mut value = json!({
+            "type": "application",
+            "bom-ref": format!("{}@{}", component.name, component.version),
+            "name": component.name,
+            "version": component.version,
+            "externalReferences": references,
+            "properties": [
+                { "name": "brewver:repository", "value": component.repo },
+                { "name": "brewver:commit", "value": component.commit },
+            ],
+        });
+        if let Some(license) = &component.license {
+            value["licenses"] = json!([{ "expression": license }]);
+        }
+        value
+    }).collect();
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.5",
+        "version": 1,
+        "metadata": {
+            "timestamp": timestamp,
+            "tools": { "components": [{ "type": "application", "name": "brewver", "version": env!("CARGO_PKG_VERSION") }] },
+        },
+        "components": components,
+    })
+}
+
+pub(crate) fn spdx(components: &[Component], timestamp: &str) -> Value {
+    let packages: Vec<Value> = components.iter().map(|component| {
+        let id: String = format!("{}-{}", component.name, component.version).chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '-' })
+            .collect();
+        let mut value = json!({
+            "SPDXID": format!("SPDXRef-Package-{}", id),
+            "name": component.name,
+            "versionInfo": component.version,
+            "downloadLocation": component.source_url.as_deref().unwrap_or("NOASSERTION"),
+            "licenseConcluded": "NOASSERTION",
+            "licenseDeclared": component.license.as_deref().unwrap_or("NOASSERTION"),
+            "copyrightText": "NOASSERTION",
+        });
+        if let Some(url) = &component.homepage {
+            value["homepage"] = json!(url);
+        }
+        if let Some(url) = component.commit_url() {
+            value["sourceInfo"] = json!(format!("Formula file from {}", url));
+        }
+        value
+    }).collect();
+    json!({
+        "spdxVersion": "SPDX-2.3",
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": "brewver-managed-formulas",
+        "documentNamespace": format!("https://github.com/agnislav/brewver/spdx/{}", timestamp),
+        "creationInfo": { "created": timestamp, "creators": [format!("Tool: brewver-{}", env!("CARGO_PKG_VERSION"))] },
+        "packages": packages,
+    })
+}
@@ -0,0 +1,234 @@
+//! Remembers the formulas brewver installed, the versions it replaced so they can be rolled
+//! back to, and a history of everything it did.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::error::{BrewverError, Result};
+
+/// A state directory, by default `$XDG_STATE_HOME/brewver` or `~/.local/state/brewver`.
+#[derive(Clone)]
+pub struct State {
+    dir: PathBuf,
+}
+
+/// Holds the lock of a state directory until dropped.
+pub struct StateLock {
+    _file: std::fs::File,
+}
+
+/// A formula version brewver installed, as recorded in `state.json`.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct ManagedFormula {
+    /// The name brew knows the formula by.
+    pub name: String,
+    pub version: String,
+    /// The repository and commit the formula file was taken from, empty for local files.
+    pub repo: String,
+    pub commit: String,
+    /// Whether the formula was pinned after installing it.
+    pub pinned: bool,
+    /// When it was installed, as a UTC timestamp like `2024-05-01T12:00:00Z`.
+    pub installed_at: String,
+}
+
+/// Something brewver did, as appended to `history.jsonl`.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct HistoryEntry {
+    /// When it happened, as a UTC timestamp like `2024-05-01T12:00:00Z`.
+    pub at: String,
+    /// `resolve`, `install`, `switch`, `upgrade`, `rollback`, `cleanup` or `failure`.
+    pub action: String,
+    /// The name brew knows the formula by.
+    pub formula: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// The version that was installed before.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl HistoryEntry {
+    /// An entry timestamped now.
+    pub fn new(action: &str, formula: &str) -> Self {
+        Self {
+            at: utc_timestamp(SystemTime::now()),
+            action: action.to_string(),
+            formula: formula.to_string(),
+            version: None,
+            from: None,
+            commit: None,
+            error: None,
+        }
+    }
+
+    pub fn version(mut self, version: &str) -> Self {
+        self.version = Some(version.to_string()).filter(|version| !version.is_empty());
+        self
+    }
+
+    pub fn from(mut self, from: Option<&str>) -> Self {
+        self.from = from.map(String::from);
+        self
+    }
+
+    pub fn commit(mut self, commit: &str) -> Self {
+        self.commit = Some(commit.to_string()).filter(|commit| !commit.is_empty());
+        self
+    }
+
+    pub fn error(mut self, error: String) -> Self {
+        self.error = Some(error);
+        self
+    }
+}
+
+impl State {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// The state directory in the default location, if the home directory is known.
+    pub fn open_default() -> Option<Self> {
+        let dir = match std::env::var_os("XDG_STATE_HOME").filter(|dir| !dir.is_empty()) {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(std::env::var_os("HOME")?).join(".local").join("state"),
+        };
+        Some(Self::new(dir.join("brewver")))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Takes the advisory lock that keeps brewver runs from changing brew at the same time,
+    /// waiting up to `wait` for another run to release it.
+    pub fn lock(&self, wait: Duration) -> Result<StateLock> {
+        std::fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join("lock");
+        let file = std::fs::OpenOptions::new().create(true).truncate(false).write(true).open(&path)?;
+        let deadline = Instant::now() + wait;
+        loop {
+            match file.try_lock() {
+                Ok(()) => return Ok(StateLock { _file: file }),
+                Err(std::fs::TryLockError::WouldBlock) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(200)),
+                Err(std::fs::TryLockError::WouldBlock) => return Err(BrewverError::AlreadyRunning(path)),
+                Err(std::fs::TryLockError::Error(e)) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// The version that was installed before brewver last replaced the formula.
+    pub fn previous_version(&self, brew_name: &str) -> Option<String> {
+        read::<String>(&self.previous_versions_path()).remove(brew_name)
+    }
+
+    pub fn record_previous_version(&self, brew_name: &str, version: &str) -> Result<()> {
+        let path = self.previous_versions_path();
+        let mut versions = read(&path);
+        versions.insert(brew_name.to_string(), version.to_string());
+        write(&path, &versions)
+    }
+
+    /// The formulas brewver installed, by name. Installing a formula again replaces its record.
+    pub fn managed_formulas(&self) -> BTreeMap<String, ManagedFormula> {
+        read(&self.managed_path())
+    }
+
+    /// Records an install, timestamped now.
+    pub fn record_managed(&self, name: &str, version: &str, repo: &str, commit: &str, pinned: bool) -> Result<()> {
+        let path = self.managed_path();
+        let mut formulas = read(&path);
+        formulas.insert(name.to_string(), ManagedFormula {
+            name: name.to_string(),
+            version: version.to_string(),
+            repo: repo.to_string(),
+            commit: commit.to_string(),
+            pinned,
+            installed_at: utc_timestamp(SystemTime::now()),
+        });
+        write(&path, &formulas)
+    }
+
+    /// Drops the record of a formula, so it is no longer managed by brewver.
+    pub fn forget_managed(&self, name: &str) -> Result<()> {
+        let path = self.managed_path();
+        let mut formulas: BTreeMap<String, ManagedFormula> = read(&path);
+        if formulas.remove(name).is_some() {
+            write(&path, &formulas)?;
+        }
+        Ok(())
+    }
+
+    /// Appends an entry to the history.
+    pub fn record_history(&self, entry: &HistoryEntry) -> Result<()> {
+        let path = self.history_path();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", serde_json::to_string(entry)?)?;
+        Ok(())
+    }
+
+    /// The history, oldest first. Lines that can't be read are skipped.
+    pub fn history(&self) -> Vec<HistoryEntry> {
+        std::fs::read_to_string(self.history_path())
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect()
+    }
+
+    fn history_path(&self) -> PathBuf {
+        self.dir.join("history.jsonl")
+    }
+
+    fn previous_versions_path(&self) -> PathBuf {
+        self.dir.join("previous_versions.json")
+    }
+
+    fn managed_path(&self) -> PathBuf {
+        self.dir.join("state.json")
+    }
+}
+
+fn read<T: serde::de::DeserializeOwned>(path: &Path) -> BTreeMap<String, T> {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+fn write<T: serde::Serialize>(path: &Path, entries: &BTreeMap<String, T>) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, serde_json::to_string_pretty(entries)?)?;
+    Ok(())
+}
+
+/// Formats a time as `YYYY-MM-DDTHH:MM:SSZ`, converting days to a date with the proleptic
+/// Gregorian calendar.
+pub fn utc_timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let (days, rest) = (secs / 86400, secs % 86400);
+
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, rest / 3600, rest % 3600 / 60, rest % 60)
+}
@@ -0,0 +1,59 @@
+//! Counters of what a run spends its requests and time on, as [`run_stats`] sums them up. They
+//! are only kept in memory, and never sent anywhere.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+static API_REQUESTS: AtomicU64 = AtomicU64::new(0);
+static OTHER_REQUESTS: AtomicU64 = AtomicU64::new(0);
+static CACHE_HITS: AtomicU64 = AtomicU64::new(0);
+static BYTES_DOWNLOADED: AtomicU64 = AtomicU64::new(0);
+static RESOLVE_MICROS: AtomicU64 = AtomicU64::new(0);
+static BREW_MICROS: AtomicU64 = AtomicU64::new(0);
+
+/// What the process spent so far, summed over its threads.
+#[derive(serde::Serialize, schemars::JsonSchema, Clone, Debug, Default)]
+pub struct RunStats {
+    /// Requests to the GitHub API, retries included.
+    pub api_requests: u64,
+    /// Requests to other hosts: raw files, the Homebrew API, bottles and source archives.
+    pub other_requests: u64,
+    /// Commits, formula files and bottles taken from the cache, and pages of history GitHub
+    /// answered were unchanged.
+    pub cache_hits: u64,
+    pub bytes_downloaded: u64,
+    /// Time spent resolving versions to commits, downloads of formula files excluded.
+    pub resolve_ms: u64,
+    /// Time spent in brew commands.
+    pub brew_ms: u64,
+}
+
+/// The requests, cache hits, downloads and time counted since the process started.
+pub fn run_stats() -> RunStats {
+    RunStats {
+        api_requests: API_REQUESTS.load(Ordering::Relaxed),
+        other_requests: OTHER_REQUESTS.load(Ordering::Relaxed),
+        cache_hits: CACHE_HITS.load(Ordering::Relaxed),
+        bytes_downloaded: BYTES_DOWNLOADED.load(Ordering::Relaxed),
+        resolve_ms: RESOLVE_MICROS.load(Ordering::Relaxed) / 1000,
+        brew_ms: BREW_MICROS.load(Ordering::Relaxed) / 1000,
+    }
+}
+
+pub(crate) fn count_request(api: bool, bytes: u64) {
+    let requests = if api { &API_REQUESTS } else { &OTHER_REQUESTS };
+    requests.fetch_add(1, Ordering::Relaxed);
+    BYTES_DOWNLOADED.fetch_add(bytes, Ordering::Relaxed);
+}
+
+pub(crate) fn count_cache_hit() {
+    CACHE_HITS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub(crate) fn add_resolve_time(elapsed: Duration) {
+    RESOLVE_MICROS.fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+}
+
+pub(crate) fn add_brew_time(elapsed: Duration) {
+    BREW_MICROS.fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+}
@@ -0,0 +1,115 @@
+//! Updates of brewver itself from its GitHub releases, for `brewver self-update`.
+//!
+//! Each release is expected to carry a binary per platform named after its target triple, e.g.
+//! `brewver-aarch64-apple-darwin`, with its SHA-256 checksum in `<binary>.sha256` and optionally a
+//! minisign signature in `<binary>.minisig`.
+
+use std::path::{Path, PathBuf};
+
+use brewver::{BrewverError, GithubClient};
+use sha2::{Digest, Sha256};
+
+/// The repository brewver is released from.
+const RELEASE_REPO: &str = "agnislav/brewver";
+
+/// A published release of brewver.
+pub(crate) struct Release {
+    pub(crate) tag: String,
+    /// The version of the tag, without its `v` prefix.
+    pub(crate) version: String,
+    assets: Vec<(String, String)>,
+}
+
+impl Release {
+    /// The latest release, as GitHub reports it.
+    pub(crate) fn latest(client: &dyn GithubClient) -> brewver::Result<Self> {
+        let url = format!("{}/repos/{}/releases/latest", client.api_url(), RELEASE_REPO);
+        let response = client.get(&url)?;
+        if !response.is_success() {
+            return Err(BrewverError::HttpStatus { url: response.url, status: response.status });
+        }
+        let json = response.json()?;
+        let Some(tag) = json.get("tag_name").and_then(|tag| tag.as_str()) else {
+            return Err(BrewverError::UnexpectedResponse { url, reason: "no tag_name".to_string() });
+        };
+        let assets = json.get("assets").and_then(|assets| assets.as_array()).into_iter().flatten()
+            .filter_map(|asset| Some((
+                asset.get("name")?.as_str()?.to_string(),
+                asset.get("browser_download_url")?.as_str()?.to_string(),
+            )))
+            .collect();
+        Ok(Release { tag: tag.to_string(), version: tag.trim_start_matches('v').to_string(), assets })
+    }
+
+    /// Whether the release is newer than the running binary.
+    pub(crate) fn is_newer(&self) -> bool {
+        let numbers = |version: &str| -> Vec<u64> { version.split('.').map(|part| part.parse().unwrap_or_default()).collect() };
+        numbers(&self.version) > numbers(env!("CARGO_PKG_VERSION"))
+    }
+
+    /// The URL of the binary for this platform.
+    pub(crate) fn binary_url(&self) -> brewver::Result<&str> {
+        let name = binary_name();
+        self.asset_url(&name).ok_or(BrewverError::NoReleaseAsset { tag: self.tag.clone(), asset: name })
+    }
+
+    fn asset_url(&self, name: &str) -> Option<&str> {
+        self.assets.iter().find(|(asset, _)| asset == name).map(|(_, url)| url.as_str())
+    }
+
+    /// Downloads the binary for this platform, checks it against its published checksum and, with
+    /// a public key, its signature, and puts it in place of the executable.
+    pub(crate) fn install(&self, client: &dyn GithubClient, public_key: Option<&Path>) -> brewver::Result<PathBuf> {
+        let url = self.binary_url()?;
+        let name = binary_name();
+        let checksum_url = self.asset_url(&format!("{}.sha256", name))
+            .ok_or(BrewverError::NoReleaseAsset { tag: self.tag.clone(), asset: format!("{}.sha256", name) })?;
+        let checksum = client.download(checksum_url)?;
+        if !checksum.is_success() {
+            return Err(BrewverError::HttpStatus { url: checksum.url, status: checksum.status });
+        }
+        let expected = checksum.body.split_whitespace().next().unwrap_or_default().to_lowercase();
+
+        // The binary is written next to the executable, so that it can be renamed over it.
+        let executable = std::env::current_exe()?.canonicalize()?;
+        let dir = executable.parent().unwrap_or(Path::new("."));
+        let download = tempfile::Builder::new().prefix(".brewver-update").tempfile_in(dir)?;
+        client.download_file(url, download.path())?;
+        let content = std::fs::read(download.path())?;
+        let found: String = Sha256::digest(&content).iter().map(|byte| format!("{:02x}", byte)).collect();
+        if found != expected {
+            return Err(BrewverError::ReleaseChecksumMismatch { url: url.to_string(), expected, found });
+        }
+
+        if let Some(public_key) = public_key {
+            let signature_url = self.asset_url(&format!("{}.minisig", name))
+                .ok_or(BrewverError::NoReleaseAsset { tag: self.tag.clone(), asset: format!("{}.minisig", name) })?;
+            let signature = client.download(signature_url)?;
+            if !signature.is_success() {
+                return Err(BrewverError::HttpStatus { url: signature.url, status: signature.status });
+            }
+            let public_key = minisign_verify::PublicKey::from_file(public_key)?;
+            let signature = minisign_verify::Signature::decode(&signature.body)?;
+            public_key.verify(&content, &signature, false)?;
+            log::info!("Signature verified: {}", signature.trusted_comment());
+        }
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(download.path(), std::fs::Permissions::from_mode(0o755))?;
+        }
+        download.persist(&executable).map_err(|e| e.error)?;
+        Ok(executable)
+    }
+}
+
+/// The name of the release binary for this platform, after its target triple.
+fn binary_name() -> String {
+    let system = match std::env::consts::OS {
+        "macos" => "apple-darwin",
+        "linux" => "unknown-linux-gnu",
+        os => os,
+    };
+    format!("brewver-{}-{}", std::env::consts::ARCH, system)
+}
@@ -0,0 +1,661 @@
+//! The resolution, download and install steps against in-memory GitHub and brew.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use brewver::mock::{MockBrew, MockClient};
+use brewver::{hook_args, to_json, Backend, BottleDownload, BrewApiResolver, BrewverError, Cache, CachePolicy, FormulaFile, FormulaResolver, Installer, MatchStrategy, ProgressEvent, RequestEstimate, Response, RestResolver, Source, VersionResolver};
+
+const COMMITS_URL: &str = "https://api.github.com/repos/Homebrew/homebrew-core/commits?path=/Formula/w/wget.rb&per_page=100";
+const OLD_COMMITS_URL: &str = "https://api.github.com/repos/Homebrew/homebrew-core/commits?path=/Formula/wget.rb&per_page=100";
+const FORMULA_URL: &str = "https://raw.githubusercontent.com/Homebrew/homebrew-core/0208aff/Formula/w/wget.rb";
+const BOTTLE_SHA256: &str = "7def9c79e5be6d7a70022168b8b099ce1e707a2fd809a60fab73de6de578884b";
+const FORMULA: &str = "class Wget < Formula\n  url \"https://ftp.gnu.org/gnu/wget/wget-1.21.3.tar.gz\"\nend\n";
+
+fn commits(messages: &[(&str, &str)]) -> String {
+    let commits: Vec<serde_json::Value> = messages.iter()
+        .map(|(sha, message)| serde_json::json!({ "sha": sha, "commit": { "message": message } }))
+        .collect();
+    serde_json::Value::Array(commits).to_string()
+}
+
+fn wget_client() -> MockClient {
+    MockClient::new()
+        .respond(COMMITS_URL, 200, commits(&[("8b1a9f3", "wget: update 1.24.5 bottle."), ("0208aff", "wget: update 1.21.3 bottle.")]))
+        .respond(OLD_COMMITS_URL, 200, "[]")
+        .respond(FORMULA_URL, 200, FORMULA)
+}
+
+#[test]
+fn resolves_the_bottle_commit_of_a_version() {
+    let client = wget_client();
+    let formula = FormulaResolver::new(&client).resolve("wget", "1.21.3").unwrap();
+
+    assert_eq!(formula.commit, "0208aff");
+    assert_eq!(formula.path, "/Formula/w/wget.rb");
+    assert_eq!(formula.url, FORMULA_URL);
+}
+
+#[test]
+fn falls_through_the_resolver_chain_until_one_finds_the_commit() {
+    let client = wget_client();
+    let chain: Vec<Box<dyn VersionResolver>> = vec![Box::new(BrewApiResolver), Box::new(RestResolver)];
+    let resolver = FormulaResolver::new(&client).chain(chain);
+    let formula = resolver.resolve("wget", "1.21.3").unwrap();
+
+    assert_eq!(formula.commit, "0208aff");
+    assert!(resolver.trail().iter().any(|step| step.starts_with("The brew-api resolver failed")));
+}
+
+#[test]
+fn reads_the_commit_history_once_for_several_versions() {
+    let client = wget_client();
+    let resolver = FormulaResolver::new(&client);
+
+    let versions: Vec<String> = resolver.versions("wget").unwrap().into_iter().map(|version| version.version).collect();
+    let old = resolver.resolve("wget", "1.21.3").unwrap();
+    let new = resolver.resolve("wget", "1.24.5").unwrap();
+
+    assert_eq!(versions, ["1.24.5", "1.21.3"]);
+    assert_eq!((old.commit.as_str(), new.commit.as_str()), ("0208aff", "8b1a9f3"));
+    assert_eq!(client.requests().iter().filter(|url| *url == COMMITS_URL).count(), 1);
+}
+
+#[test]
+fn resolves_latest_to_the_newest_version_or_one_before_it() {
+    let client = wget_client();
+
+    let latest = FormulaResolver::new(&client).resolve("wget", "latest").unwrap();
+    let previous = FormulaResolver::new(&client).n_back(1).resolve("wget", "latest").unwrap();
+    let too_far = FormulaResolver::new(&client).n_back(2).resolve("wget", "latest");
+
+    assert_eq!((latest.version.as_str(), latest.commit.as_str()), ("1.24.5", "8b1a9f3"));
+    assert_eq!((previous.version.as_str(), previous.commit.as_str()), ("1.21.3", "0208aff"));
+    assert!(matches!(too_far, Err(BrewverError::NotEnoughVersions { found: 2, n_back: 2, .. })));
+}
+
+#[test]
+fn resolves_a_pattern_to_the_newest_version_matching_it() {
+    let client = wget_client();
+    let resolve = |pattern: &str| FormulaResolver::new(&client).resolve("wget", pattern).map(|formula| formula.version);
+
+    assert_eq!(resolve("1.*").unwrap(), "1.24.5");
+    assert_eq!(resolve("1.21.?").unwrap(), "1.21.3");
+    assert_eq!(resolve(">=1.20, <1.24").unwrap(), "1.21.3");
+    assert_eq!(resolve("~1.24").unwrap(), "1.24.5");
+    assert!(matches!(resolve("^2"), Err(BrewverError::NoMatchingVersion { .. })));
+}
+
+#[test]
+fn reports_a_version_without_a_bottle_commit() {
+    let client = wget_client();
+    let result = FormulaResolver::new(&client).resolve("wget", "1.0");
+
+    assert!(matches!(result, Err(BrewverError::CommitNotFound { ref version, .. }) if version == "1.0"));
+}
+
+#[test]
+fn caches_the_commit_of_an_exact_revision_apart_from_the_newest_one() {
+    let dir = tempfile::tempdir().unwrap();
+    let client = MockClient::new()
+        .respond(COMMITS_URL, 200, commits(&[("a1b2c3d", "wget: update 1.21.3_1 bottle."), ("0208aff", "wget: update 1.21.3 bottle.")]))
+        .respond(OLD_COMMITS_URL, 200, "[]")
+        .with_cache(Cache::new(dir.path()));
+
+    let newest = FormulaResolver::new(&client).resolve("wget", "1.21.3").unwrap();
+    let exact = FormulaResolver::new(&client).revision(Some(0)).resolve("wget", "1.21.3").unwrap();
+
+    assert_eq!(newest.commit, "a1b2c3d");
+    assert_eq!(exact.commit, "0208aff");
+}
+
+#[test]
+fn keeps_the_cache_whole_when_processes_write_it_at_once() {
+    let dir = tempfile::tempdir().unwrap();
+    let writers: Vec<_> = (0..8)
+        .map(|_| {
+            let dir = dir.path().to_path_buf();
+            std::thread::spawn(move || {
+                let client = wget_client().with_cache(Cache::new(dir));
+                let formula = FormulaResolver::new(&client).resolve("wget", "1.21.3").unwrap();
+                formula.download(&client).unwrap();
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.join().unwrap();
+    }
+
+    let client = MockClient::new().offline(true).with_cache(Cache::new(dir.path()));
+    let formula = FormulaResolver::new(&client).resolve("wget", "1.21.3").unwrap();
+
+    assert_eq!(formula.commit, "0208aff");
+    assert_eq!(formula.download(&client).unwrap().contents().unwrap(), FORMULA);
+}
+
+#[test]
+fn sums_up_the_entries_of_the_cache() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = Cache::new(dir.path());
+    assert_eq!(cache.summary().entries, 0);
+
+    let client = wget_client().with_cache(Cache::new(dir.path()));
+    let formula = FormulaResolver::new(&client).resolve("wget", "1.21.3").unwrap();
+    formula.download(&client).unwrap();
+    let summary = cache.summary();
+
+    assert_eq!(summary.entries, 2);
+    assert!(summary.bytes >= FORMULA.len() as u64);
+    assert!(summary.oldest.is_some() && summary.oldest <= summary.newest);
+}
+
+#[test]
+fn takes_the_cache_before_after_or_instead_of_the_network_as_the_policy_says() {
+    let dir = tempfile::tempdir().unwrap();
+    let warm = wget_client().with_cache(Cache::new(dir.path()));
+    FormulaResolver::new(&warm).resolve("wget", "1.21.3").unwrap();
+
+    let moved = MockClient::new()
+        .respond(COMMITS_URL, 200, commits(&[("c0ffee1", "wget: update 1.21.3 bottle.")]))
+        .respond(OLD_COMMITS_URL, 200, "[]");
+    let cached_first = moved.with_cache(Cache::new(dir.path()));
+    assert_eq!(FormulaResolver::new(&cached_first).resolve("wget", "1.21.3").unwrap().commit, "0208aff");
+
+    let moved = MockClient::new()
+        .respond(COMMITS_URL, 200, commits(&[("c0ffee1", "wget: update 1.21.3 bottle.")]))
+        .respond(OLD_COMMITS_URL, 200, "[]");
+    let network_only = moved.with_cache(Cache::new(dir.path()).policy(CachePolicy::NetworkOnly));
+    assert_eq!(FormulaResolver::new(&network_only).resolve("wget", "1.21.3").unwrap().commit, "c0ffee1");
+
+    let down = MockClient::new().respond(COMMITS_URL, 503, "").respond(OLD_COMMITS_URL, 503, "");
+    let fallback = down.with_cache(Cache::new(dir.path()).policy(CachePolicy::NetworkThenCache));
+    assert_eq!(FormulaResolver::new(&fallback).resolve("wget", "1.21.3").unwrap().commit, "c0ffee1");
+
+    let empty = tempfile::tempdir().unwrap();
+    let down = MockClient::new().respond(COMMITS_URL, 503, "").respond(OLD_COMMITS_URL, 503, "");
+    let no_fallback = down.with_cache(Cache::new(empty.path()).policy(CachePolicy::NetworkThenCache));
+    assert!(FormulaResolver::new(&no_fallback).resolve("wget", "1.21.3").is_err());
+}
+
+#[test]
+fn rejects_an_empty_formula_name() {
+    let client = MockClient::new();
+    let resolver = FormulaResolver::new(&client);
+
+    assert!(matches!(resolver.resolve("", "1.0"), Err(BrewverError::EmptyName(_))));
+    assert!(matches!(resolver.versions("homebrew/core/"), Err(BrewverError::EmptyName(_))));
+}
+
+#[test]
+fn suggests_formulas_close_to_a_name_that_was_never_there() {
+    let client = MockClient::new()
+        .respond("https://api.github.com/repos/Homebrew/homebrew-core/commits?path=/Formula/w/wgett.rb&per_page=100", 200, "[]")
+        .respond("https://api.github.com/repos/Homebrew/homebrew-core/commits?path=/Formula/wgett.rb&per_page=100", 200, "[]")
+        .respond(
+            "https://api.github.com/repos/Homebrew/homebrew-core/git/trees/HEAD?recursive=1",
+            200,
+            r#"{"tree": [{"path": "Formula/w/wget.rb"}, {"path": "Formula/w/wget2.rb"}, {"path": "Formula/j/jq.rb"}, {"path": "Casks/w/wgett.rb"}]}"#,
+        );
+
+    let result = FormulaResolver::new(&client).resolve("wgett", "1.21.3");
+
+    assert!(matches!(result, Err(BrewverError::UnknownFormula { ref suggestions, .. }) if suggestions == &["wget", "wget2"]));
+}
+
+#[test]
+fn records_the_steps_of_a_resolution() {
+    let client = wget_client();
+    let resolver = FormulaResolver::new(&client);
+    resolver.resolve("wget", "1.21.3").unwrap();
+
+    let trail = resolver.trail();
+
+    assert!(trail[1].starts_with("Probed /Formula/w/wget.rb: read 1 page"), "{:?}", trail);
+    assert!(trail[2].starts_with("Matched commit 0208aff at /Formula/w/wget.rb"), "{:?}", trail);
+    assert!(trail[2].ends_with("its message is the bottle commit of the version"), "{:?}", trail);
+}
+
+#[test]
+fn follows_the_formula_file_to_where_it_was_moved_from() {
+    let commit_url = "https://api.github.com/repos/Homebrew/homebrew-core/commits/0208aff";
+    let moved_url = "https://api.github.com/repos/Homebrew/homebrew-core/commits?path=/Library/Formula/wget.rb&per_page=100";
+    let client = wget_client()
+        .respond(commit_url, 200, r#"{"files": [{"filename": "Formula/w/wget.rb", "status": "renamed", "previous_filename": "Library/Formula/wget.rb"}]}"#)
+        .respond(moved_url, 200, commits(&[("5f2c1e0", "wget: update 1.16 bottle.")]));
+
+    let formula = FormulaResolver::new(&client).source(Source::Api).resolve("wget", "1.16").unwrap();
+
+    assert_eq!(formula.commit, "5f2c1e0");
+    assert_eq!(formula.path, "/Library/Formula/wget.rb");
+}
+
+#[test]
+fn accepts_version_bump_commits_with_the_bump_strategy() {
+    let client = MockClient::new()
+        .respond(COMMITS_URL, 200, commits(&[("8b1a9f3", "wget: update 1.24.5 bottle."), ("c41d7e2", "wget: update 1.21.2 -> 1.21.3")]))
+        .respond(OLD_COMMITS_URL, 200, "[]");
+
+    let bottles_only = FormulaResolver::new(&client).resolve("wget", "1.21.3");
+    let formula = FormulaResolver::new(&client).match_strategy(MatchStrategy::Bump).resolve("wget", "1.21.3").unwrap();
+
+    assert!(matches!(bottles_only, Err(BrewverError::CommitNotFound { .. })));
+    assert_eq!(formula.commit, "c41d7e2");
+}
+
+#[test]
+fn finds_the_newest_commit_whose_file_declares_the_version() {
+    let history = [("d9e4a01", "1.24.5"), ("b3c8f27", "1.21.3"), ("0208aff", "1.21.3"), ("7a61e5d", "1.20")];
+    let mut client = MockClient::new()
+        .respond(COMMITS_URL, 200, commits(&history.map(|(sha, _)| (sha, "wget: fix build"))))
+        .respond(OLD_COMMITS_URL, 200, "[]");
+    for (sha, version) in history {
+        let url = format!("https://raw.githubusercontent.com/Homebrew/homebrew-core/{}/Formula/w/wget.rb", sha);
+        client = client.respond(&url, 200, format!("class Wget < Formula\n  url \"https://ftp.gnu.org/gnu/wget/wget-{}.tar.gz\"\nend\n", version));
+    }
+
+    let formula = FormulaResolver::new(&client).source(Source::Api).backend(Backend::Content).resolve("wget", "1.21.3").unwrap();
+
+    assert_eq!(formula.commit, "b3c8f27");
+}
+
+#[test]
+fn matches_the_version_in_the_source_url_when_the_formula_version_differs() {
+    let history = [("d9e4a01", "1.24.5"), ("b3c8f27", "1.21.3"), ("7a61e5d", "1.20")];
+    let mut client = MockClient::new()
+        .respond(COMMITS_URL, 200, commits(&history.map(|(sha, _)| (sha, "wget: fix build"))))
+        .respond(OLD_COMMITS_URL, 200, "[]");
+    for (sha, version) in history {
+        let url = format!("https://raw.githubusercontent.com/Homebrew/homebrew-core/{}/Formula/w/wget.rb", sha);
+        let contents = format!("class Wget < Formula\n  url \"https://ftp.gnu.org/gnu/wget/wget-{}.tar.gz\"\n  version \"{}-gnu\"\nend\n", version, version);
+        client = client.respond(&url, 200, contents);
+    }
+
+    let by_version = FormulaResolver::new(&client).source(Source::Api).backend(Backend::Content).resolve("wget", "1.21.3");
+    let formula = FormulaResolver::new(&client).source(Source::Api).backend(Backend::Content).match_upstream_url(true).resolve("wget", "1.21.3").unwrap();
+
+    assert!(matches!(by_version, Err(BrewverError::CommitNotFound { .. })));
+    assert_eq!(formula.commit, "b3c8f27");
+    let file = formula.download(&client).unwrap();
+    assert_eq!(file.info().unwrap().version.as_deref(), Some("1.21.3-gnu"));
+    formula.check_version(&file).unwrap();
+}
+
+#[test]
+fn tells_rate_limit_errors_apart_from_missing_versions() {
+    let mut headers = BTreeMap::new();
+    headers.insert("x-ratelimit-remaining".to_string(), "0".to_string());
+    headers.insert("x-ratelimit-reset".to_string(), "1700000000".to_string());
+    let client = MockClient::new().respond_with(Response {
+        url: COMMITS_URL.to_string(),
+        status: 403,
+        headers,
+        body: r#"{"message": "API rate limit exceeded for 127.0.0.1."}"#.to_string(),
+    });
+    let result = FormulaResolver::new(&client).source(Source::Api).resolve("wget", "1.21.3");
+
+    assert!(matches!(result, Err(BrewverError::RateLimited { reset_at: 1700000000, .. })));
+}
+
+#[test]
+fn downloads_the_formula_file_of_the_commit() {
+    let client = wget_client();
+    let formula = FormulaResolver::new(&client).resolve("wget", "1.21.3").unwrap();
+    let file = formula.download(&client).unwrap();
+
+    assert_eq!(file.contents().unwrap(), FORMULA);
+    assert_eq!(client.requests().last().map(String::as_str), Some(FORMULA_URL));
+}
+
+#[test]
+fn refuses_a_formula_file_whose_blob_github_doesnt_list() {
+    let contents_url = "https://api.github.com/repos/Homebrew/homebrew-core/contents/Formula/w/wget.rb?ref=0208aff";
+    let client = wget_client().respond(contents_url, 200, r#"{"sha": "95d09f2b10159347eece71399a7e2e907ea3df4f"}"#);
+    let formula = FormulaResolver::new(&client).resolve("wget", "1.21.3").unwrap();
+    let file = formula.download(&client).unwrap();
+
+    let result = formula.verify_blob(&client, &file);
+
+    assert!(matches!(result, Err(BrewverError::BlobMismatch { ref found, .. }) if found == "3903b79b35f9b2e3518e271f234529afb5ca2d75"));
+}
+
+#[test]
+fn cautions_against_a_version_upstream_deprecated() {
+    let client = wget_client();
+    let formula = FormulaResolver::new(&client).resolve("wget", "1.21.3").unwrap();
+    let file = FormulaFile::new("wget", "class Wget < Formula\n  deprecate! date: \"2024-01-01\", because: :unmaintained\nend\n").unwrap();
+
+    let cautions = formula.cautions(&file).unwrap();
+
+    assert_eq!(cautions, ["wget 1.21.3 is deprecated upstream since 2024-01-01 (unmaintained)"]);
+}
+
+#[test]
+fn installs_in_place_of_the_previous_version() {
+    let cellar = tempfile::tempdir().unwrap();
+    std::fs::create_dir(cellar.path().join("1.20")).unwrap();
+    let client = wget_client();
+    let formula = FormulaResolver::new(&client).resolve("wget", "1.21.3").unwrap();
+    let file = formula.download(&client).unwrap();
+    let brew = MockBrew::new()
+        .respond("list --versions wget", "wget 1.20\n")
+        .respond("--cellar wget", &cellar.path().display().to_string());
+
+    Installer::new().brew(brew.clone()).install(&formula, &file).unwrap();
+
+    let formula_file = file.path().display().to_string();
+    assert_eq!(brew.commands(), ["list --versions wget", "uses --installed wget", "unlink wget", &format!("install {}", formula_file), "--cellar wget"]);
+    assert!(!cellar.path().join("1.20").exists());
+}
+
+#[test]
+fn stops_a_running_service_while_replacing_its_version() {
+    let cellar = tempfile::tempdir().unwrap();
+    std::fs::create_dir(cellar.path().join("1.24.5")).unwrap();
+    let client = wget_client();
+    let formula = FormulaResolver::new(&client).resolve("wget", "1.21.3").unwrap();
+    let file = formula.download(&client).unwrap();
+    let brew = MockBrew::new()
+        .respond("list --versions wget", "wget 1.24.5\n")
+        .respond("services list --json", r#"[{"name": "wget", "status": "started"}]"#)
+        .respond("--cellar wget", &cellar.path().display().to_string());
+
+    Installer::new().brew(brew.clone()).restart_services(true).install(&formula, &file).unwrap();
+
+    let commands = brew.commands();
+    let position = |command: &str| commands.iter().position(|run| run.starts_with(command)).unwrap();
+    assert!(position("services stop wget") < position("unlink wget"));
+    assert!(position("install ") < position("services start wget"));
+}
+
+#[test]
+fn cleans_up_the_kegs_other_than_the_kept_and_linked_versions() {
+    let cellar = tempfile::tempdir().unwrap();
+    for version in ["1.20", "1.21.3", "1.24.5"] {
+        std::fs::create_dir(cellar.path().join(version)).unwrap();
+    }
+    let brew = MockBrew::new()
+        .respond("list --versions wget", "wget 1.20 1.21.3 1.24.5\n")
+        .respond("info --json=v2 wget", r#"{"formulae": [{"linked_keg": "1.24.5"}]}"#)
+        .respond("--cellar wget", &cellar.path().display().to_string());
+
+    let removed = Installer::new().brew(brew.clone()).cleanup("wget", "1.21.3").unwrap();
+
+    assert_eq!(removed, ["1.20"]);
+    assert!(!cellar.path().join("1.20").exists());
+    assert!(cellar.path().join("1.24.5").exists());
+    assert_eq!(brew.commands().last().map(String::as_str), Some("cleanup wget"));
+}
+
+#[test]
+fn refuses_to_replace_a_version_other_formulas_depend_on() {
+    let client = wget_client();
+    let formula = FormulaResolver::new(&client).resolve("wget", "1.21.3").unwrap();
+    let file = formula.download(&client).unwrap();
+    let brew = MockBrew::new()
+        .respond("list --versions wget", "wget 1.20\n")
+        .respond("uses --installed wget", "curlie\nhttpie\n");
+
+    let result = Installer::new().brew(brew.clone()).install(&formula, &file);
+
+    assert!(matches!(result, Err(BrewverError::HasDependents { ref dependents, .. }) if dependents == &["curlie", "httpie"]));
+    assert_eq!(brew.commands(), ["list --versions wget", "uses --installed wget"]);
+}
+
+#[test]
+fn relinks_the_previous_version_when_the_install_fails() {
+    let client = wget_client();
+    let formula = FormulaResolver::new(&client).resolve("wget", "1.21.3").unwrap();
+    let file = formula.download(&client).unwrap();
+    let brew = MockBrew::new()
+        .respond("list --versions wget", "wget 1.20\n")
+        .fail("install");
+
+    let result = Installer::new().brew(brew.clone()).install(&formula, &file);
+
+    assert!(matches!(result, Err(BrewverError::BrewFailed { .. })));
+    assert_eq!(brew.commands().last().map(String::as_str), Some("link wget"));
+}
+
+#[test]
+fn fails_when_brew_doesnt_list_the_installed_version() {
+    let client = wget_client();
+    let formula = FormulaResolver::new(&client).resolve("wget", "1.21.3").unwrap();
+    let file = formula.download(&client).unwrap();
+    let brew = MockBrew::new().respond("list --versions wget", "wget 1.20\n");
+
+    let result = Installer::new().brew(brew.clone()).force_remove(true).verify(true).install(&formula, &file);
+
+    assert!(matches!(result, Err(BrewverError::NotInstalled { ref installed, .. }) if installed == &["1.20"]));
+    assert_eq!(brew.commands().last().map(String::as_str), Some("link wget"));
+}
+
+#[test]
+fn pours_the_bottle_declared_in_the_formula_file() {
+    let client = wget_client();
+    let formula = FormulaResolver::new(&client).resolve("wget", "1.21.3").unwrap();
+    let contents = format!("class Wget < Formula\n  bottle do\n    rebuild 1\n    sha256 cellar: :any, all: \"{}\"\n  end\nend\n", BOTTLE_SHA256);
+    let file = FormulaFile::new("wget", &contents).unwrap();
+    let bottle = BottleDownload::find(&formula, &file).unwrap().unwrap();
+    assert_eq!(bottle.url, format!("https://ghcr.io/v2/homebrew/core/wget/blobs/sha256:{}", BOTTLE_SHA256));
+    assert_eq!(bottle.file_name, "wget--1.21.3.all.bottle.1.tar.gz");
+
+    let client = client.respond(&bottle.url, 200, "bottle");
+    let bottle_file = bottle.download(&client).unwrap();
+    let brew = MockBrew::new();
+    Installer::new().brew(brew.clone()).install_bottle(&formula, &file, bottle_file.path()).unwrap();
+
+    assert_eq!(std::fs::read_to_string(bottle_file.path()).unwrap(), "bottle");
+    assert_eq!(brew.commands(), ["list --versions wget", &format!("install {}", bottle_file.path().display())]);
+}
+
+#[test]
+fn refuses_a_bottle_that_doesnt_match_its_checksum() {
+    let client = wget_client();
+    let formula = FormulaResolver::new(&client).resolve("wget", "1.21.3").unwrap();
+    let contents = format!("class Wget < Formula\n  bottle do\n    sha256 all: \"{}\"\n  end\nend\n", BOTTLE_SHA256);
+    let file = FormulaFile::new("wget", &contents).unwrap();
+    let bottle = BottleDownload::find(&formula, &file).unwrap().unwrap();
+    let client = client.respond(&bottle.url, 200, "tampered");
+
+    let result = bottle.download(&client);
+
+    assert!(matches!(result, Err(BrewverError::BottleChecksumMismatch { .. })));
+}
+
+#[test]
+fn downloads_a_bottle_missing_from_the_mirror_from_the_registry() {
+    let client = wget_client();
+    let formula = FormulaResolver::new(&client).resolve("wget", "1.21.3").unwrap();
+    let contents = format!("class Wget < Formula\n  bottle do\n    sha256 all: \"{}\"\n  end\nend\n", BOTTLE_SHA256);
+    let file = FormulaFile::new("wget", &contents).unwrap();
+    let canonical = BottleDownload::find(&formula, &file).unwrap().unwrap();
+    let mirrored = BottleDownload {
+        url: format!("https://mirrors.example.com/homebrew-bottles/{}", canonical.file_name),
+        canonical_url: Some(canonical.url.clone()),
+        ..canonical.clone()
+    };
+    let client = client.respond(&mirrored.url, 404, "").respond(&canonical.url, 200, "bottle");
+
+    let bottle_file = mirrored.download(&client).unwrap();
+
+    assert_eq!(std::fs::read_to_string(bottle_file.path()).unwrap(), "bottle");
+}
+
+#[test]
+fn plans_the_brew_commands_without_running_them() {
+    let client = wget_client();
+    let formula = FormulaResolver::new(&client).resolve("wget", "1.21.3").unwrap();
+    let brew = MockBrew::new();
+
+    let plan = Installer::new().brew(brew.clone()).pin(true).plan(&formula, "wget.rb");
+
+    assert_eq!(plan, [vec!["brew", "install", "wget.rb"], vec!["brew", "pin", "wget"]]);
+    assert_eq!(brew.commands(), ["list --versions wget"]);
+}
+
+#[test]
+fn splits_hook_commands_like_a_shell_without_running_one() {
+    let args = |command: &str| hook_args(command, false, &[]).unwrap();
+    assert_eq!(args("brew services  stop postgresql@14"), ["brew", "services", "stop", "postgresql@14"]);
+    assert_eq!(args(r#"notify 'a  b' "c \"d\" \e" f\ g ''"#), ["notify", "a  b", r#"c "d" \e"#, "f g", ""]);
+    assert_eq!(args(r#"echo pre'fix'"ed" "$HOME" '>' out"#), ["echo", "prefixed", "$HOME", ">", "out"]);
+    assert!(matches!(hook_args("echo 'open", false, &[]), Err(BrewverError::InvalidHook { .. })));
+    assert!(matches!(hook_args("   ", false, &[]), Err(BrewverError::InvalidHook { .. })));
+}
+
+#[test]
+fn expands_variables_in_hook_commands_only_when_asked() {
+    let env = [("BREWVER_FORMULA", "postgresql@14"), ("BREWVER_VERSION", "14.9 beta")];
+    let command = r#"log $BREWVER_FORMULA "${BREWVER_VERSION}!" '$BREWVER_FORMULA' \$BREWVER_VERSION $BREWVER_UNSET_VARIABLE"#;
+    assert_eq!(
+        hook_args(command, false, &env).unwrap(),
+        ["log", "$BREWVER_FORMULA", "${BREWVER_VERSION}!", "$BREWVER_FORMULA", "$BREWVER_VERSION", "$BREWVER_UNSET_VARIABLE"]
+    );
+    assert_eq!(hook_args(command, true, &env).unwrap(), ["log", "postgresql@14", "14.9 beta!", "$BREWVER_FORMULA", "$BREWVER_VERSION", ""]);
+    assert!(matches!(hook_args("echo 5$", true, &env), Err(BrewverError::InvalidHook { .. })));
+}
+
+#[test]
+fn prints_the_same_json_compact_or_pretty() {
+    let client = wget_client();
+    let formula = FormulaResolver::new(&client).resolve("wget", "1.21.3").unwrap();
+    let compact = to_json(&formula, true).unwrap();
+    let pretty = to_json(&formula, false).unwrap();
+    assert!(!compact.contains('\n'));
+    assert!(pretty.lines().count() > 1);
+    assert_ne!(compact, pretty);
+    let value = |json: &str| serde_json::from_str::<serde_json::Value>(json).unwrap();
+    assert_eq!(value(&compact), value(&pretty));
+}
+
+#[test]
+fn describes_the_json_it_prints_with_a_schema_the_output_matches() {
+    let client = wget_client();
+    let estimate = FormulaResolver::new(&client).estimate_requests("wget", "1.21.3").unwrap();
+    let schema = serde_json::to_value(schemars::schema_for!(RequestEstimate)).unwrap();
+    let output: serde_json::Value = serde_json::from_str(&to_json(&estimate, true).unwrap()).unwrap();
+    assert!(jsonschema::is_valid(&schema, &output), "{output} doesn't match {schema}");
+
+    let mut broken = output.clone();
+    broken["requests"] = serde_json::json!("many");
+    assert!(!jsonschema::is_valid(&schema, &broken));
+}
+
+#[test]
+fn reports_the_progress_as_json_lines_in_order() {
+    let lines = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let sink = lines.clone();
+    brewver::set_progress(move |event| sink.lock().unwrap().push(to_json(event, true).unwrap()));
+
+    let client = wget_client();
+    FormulaResolver::new(&client).resolve("wget", "1.21.3").unwrap();
+
+    let events: Vec<serde_json::Value> = lines.lock().unwrap().iter().map(|line| serde_json::from_str(line).unwrap()).collect();
+    let phases: Vec<&str> = events.iter().map(|event| event["phase"].as_str().unwrap()).collect();
+    let resolve = events.iter().position(|event| event["phase"] == "resolve" && event["name"] == "wget").unwrap();
+    let commit = events.iter().position(|event| event["phase"] == "commit" && event["commit"] == "0208aff").unwrap();
+    assert!(resolve < commit, "{phases:?}");
+    assert_eq!(events[commit]["repo"], "Homebrew/homebrew-core");
+
+    let download = ProgressEvent::DownloadProgress { url: FORMULA_URL.to_string(), received: 1234, total: Some(5678) };
+    assert_eq!(to_json(&download, true).unwrap(), format!(r#"{{"phase":"download","url":"{FORMULA_URL}","bytes":1234,"total":5678}}"#));
+}
+
+#[test]
+fn retries_a_page_of_history_that_is_slower_than_the_page_timeout() {
+    use std::io::{Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = std::thread::spawn(move || {
+        for (connection, stream) in listener.incoming().take(2).enumerate() {
+            let mut stream = stream.unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let read = stream.read(&mut buffer).unwrap();
+                request.extend_from_slice(&buffer[..read]);
+            }
+            if connection == 0 {
+                // The first page never arrives within the resolve timeout, only a retry answers.
+                std::thread::spawn(move || {
+                    std::thread::sleep(Duration::from_secs(60));
+                    drop(stream);
+                });
+                continue;
+            }
+            let body = commits(&[("0208aff", "wget: update 1.21.3 bottle.")]);
+            let response = format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body);
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+
+    let client = brewver::Client::builder()
+        .github_api_url(Some(format!("http://{address}")))
+        .resolve_timeout(Some(Duration::from_secs(30)))
+        .page_timeout(Some(Duration::from_millis(300)))
+        .retries(1)
+        .build()
+        .unwrap();
+    let page = format!("http://{address}/repos/Homebrew/homebrew-core/commits?path=/Formula/w/wget.rb&per_page=100&page=2");
+    let started = std::time::Instant::now();
+    let response = client.get_all(&[page]).pop().unwrap().unwrap();
+
+    assert_eq!(response.status, 200);
+    assert!(response.body.contains("0208aff"));
+    assert!(started.elapsed() < Duration::from_secs(10), "took {:?}", started.elapsed());
+    server.join().unwrap();
+}
+
+#[test]
+fn parses_every_field_of_a_formula_file() {
+    let contents = r#"class Foo < Formula
+  desc "Does foo things"
+  homepage "https://foo.example"
+  url "https://foo.example/releases/foo-2.1.tar.gz"
+  sha256 "5f8c1e2a"
+  license "MIT"
+  head "https://github.com/foo/foo.git", branch: "main"
+  keg_only :versioned_formula
+
+  bottle do
+    root_url "https://ghcr.io/v2/foo/bar"
+    rebuild 1
+    sha256 cellar: :any, arm64_sonoma: "deadbeef"
+    sha256 x86_64_linux: "cafef00d"
+  end
+
+  deprecate! date: "2024-01-01", because: :unmaintained
+  disable! date: "2025-01-01", because: "is broken", replacement: "bar"
+
+  depends_on "pkg-config" => :build
+  depends_on "openssl@3"
+
+  def caveats
+    <<~EOS
+      Run foo --init first.
+    EOS
+  end
+end
+"#;
+    let info = FormulaFile::new("foo", contents).unwrap().info().unwrap();
+
+    assert_eq!(info.version.as_deref(), Some("2.1"));
+    assert_eq!(info.desc.as_deref(), Some("Does foo things"));
+    assert_eq!(info.homepage.as_deref(), Some("https://foo.example"));
+    assert_eq!(info.url.as_deref(), Some("https://foo.example/releases/foo-2.1.tar.gz"));
+    assert_eq!(info.sha256.as_deref(), Some("5f8c1e2a"));
+    assert_eq!(info.license.as_deref(), Some("MIT"));
+    assert_eq!(info.head.as_deref(), Some("https://github.com/foo/foo.git"));
+    assert_eq!(info.keg_only.as_deref(), Some("versioned_formula"));
+    assert_eq!(info.dependencies, ["pkg-config", "openssl@3"]);
+    assert_eq!(info.bottle_root_url.as_deref(), Some("https://ghcr.io/v2/foo/bar"));
+    assert_eq!(info.bottle_rebuild, Some(1));
+    let bottles: Vec<(&str, &str, Option<&str>)> = info.bottles.iter().map(|bottle| (bottle.tag.as_str(), bottle.sha256.as_str(), bottle.cellar.as_deref())).collect();
+    assert_eq!(bottles, [("arm64_sonoma", "deadbeef", Some(":any")), ("x86_64_linux", "cafef00d", None)]);
+    let deprecated = info.deprecated.unwrap();
+    assert_eq!((deprecated.date.as_deref(), deprecated.because.as_deref()), (Some("2024-01-01"), Some("unmaintained")));
+    let disabled = info.disabled.unwrap();
+    assert_eq!((disabled.because.as_deref(), disabled.replacement.as_deref()), (Some("is broken"), Some("bar")));
+    assert_eq!(info.caveats.as_deref(), Some("Run foo --init first."));
+}